@@ -1,7 +1,12 @@
 use std::io::Seek;
 
 // use bundle::{index::Index, Bundle};
-use criterion::{criterion_group, criterion_main, Criterion};
+use bundle::{
+    index::{ExtractOptions, IndexBuilder},
+    Bundle,
+};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rayon::prelude::*;
 
 fn benchmark(c: &mut Criterion) {
     // let mut group = c.benchmark_group("Reader vs Slice");
@@ -26,5 +31,78 @@ fn benchmark(c: &mut Criterion) {
     //     });
 }
 
-criterion_group!(benches, benchmark);
+/// Loads and decompresses the same bundle twice per thread, like extract does
+/// across bundles, with and without chunk level parallelism inside each task.
+fn nested_parallelism(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Nested vs Flattened");
+    let slice = include_bytes!("../bundle/resources/Shared.bundle.bin");
+    let tasks = rayon::current_num_threads() * 2;
+
+    group.bench_function("nested", |b| {
+        b.iter(|| {
+            (0..tasks).into_par_iter().for_each(|_| {
+                let bundle: Bundle<Vec<u8>> = Bundle::from_slice(slice).unwrap();
+                drop(bundle.decompress().unwrap());
+            })
+        })
+    });
+    group.bench_function("flattened", |b| {
+        b.iter(|| {
+            (0..tasks).into_par_iter().for_each(|_| {
+                let bundle: Bundle<Vec<u8>> = Bundle::from_slice(slice).unwrap();
+                drop(bundle.decompress_sequential().unwrap());
+            })
+        })
+    });
+}
+
+/// Compresses 64 MiB into a bundle, thousands of chunks at the default
+/// chunk length.
+fn compress(c: &mut Criterion) {
+    let data: Vec<u8> = (0..64 * 1024 * 1024u32).map(|b| (b % 251) as u8).collect();
+
+    let mut group = c.benchmark_group("Compress");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.sample_size(10);
+    group.bench_function("new", |b| b.iter(|| Bundle::new(data.clone()).unwrap()));
+    group.finish();
+}
+
+/// Extracts a generated fixture of 8 bundles with 64 files each, end to end
+/// from reading the bundles to writing every file.
+fn extract(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join("poe2kit-bench-extract");
+    let out = dir.join("out");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut builder = IndexBuilder::new().bundle_size(64 * 64 * 1024);
+    for i in 0..8 * 64u32 {
+        let bytes: Vec<u8> = (0..64 * 1024u32).map(|b| (b * i % 251) as u8).collect();
+        builder.add_file(&format!("data/{}/{i}.bin", i % 8), bytes);
+    }
+    let (index, bundles) = builder.build().unwrap();
+
+    std::fs::create_dir_all(dir.join("Bundles2")).unwrap();
+    for bundle in &bundles {
+        let path = dir.join(format!("Bundles2/{}.bundle.bin", bundle.path()));
+        std::fs::write(path, bundle.bundle().to_vec()).unwrap();
+    }
+
+    let options = ExtractOptions::default();
+    let mut group = c.benchmark_group("Extract");
+    group.throughput(Throughput::Bytes(index.total_uncompressed_size() as u64));
+    group.sample_size(10);
+    group.bench_function("extract", |b| {
+        b.iter(|| {
+            index
+                .extract(index.iter_bundles(), &dir, &out, &options)
+                .unwrap()
+        })
+    });
+    group.finish();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(benches, benchmark, nested_parallelism, compress, extract);
 criterion_main!(benches);
@@ -0,0 +1,191 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use oodle_sys::OodleLZ_SeekTable;
+
+use crate::{
+    decompress_chunks,
+    util::{read_bytes, read_i32, read_i64, read_u32},
+    verify_parts, Bundle, BundleError, DecodeThreadPhase,
+};
+
+/// A parsed bundle whose compressed chunks borrow from the buffer it was read
+/// from, e.g. a memory-mapped file, instead of being copied like [`Bundle`]'s.
+#[derive(Debug, Clone)]
+pub struct BundleRef<'a, T = Arc<[u8]>> {
+    uncompressed_size: u32,
+    compressed_size: u32,
+    seek_table_size: u32,
+    seek_table: OodleLZ_SeekTable,
+    seek_chunk_comp_lens: Vec<u32>,
+    raw_crcs: Option<Vec<u32>>,
+    chunks: Vec<&'a [u8]>,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T> BundleRef<'a, T> {
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Whether every chunk can be decoded on its own, see
+    /// [`Bundle::chunks_independent`].
+    pub fn chunks_independent(&self) -> bool {
+        self.seek_table.seekChunksIndependent != 0
+    }
+
+    /// Checks that the header, seek table and chunks agree with each other, see
+    /// [`Bundle::verify`].
+    pub fn verify(&self) -> Result<(), BundleError> {
+        verify_parts(
+            self.uncompressed_size,
+            self.compressed_size,
+            self.seek_table_size,
+            &self.seek_table,
+            &self.seek_chunk_comp_lens,
+            self.chunks.len(),
+            self.raw_crcs.as_ref().map(|crcs| crcs.len()),
+        )
+    }
+
+    /// Copies the chunks out of the source buffer.
+    pub fn into_owned(self) -> Bundle<T> {
+        let mut seek_table = self.seek_table;
+        let seek_chunk_comp_lens: Arc<[u32]> = self.seek_chunk_comp_lens.into();
+        seek_table.seekChunkCompLens = seek_chunk_comp_lens.as_ptr() as *mut _;
+        let raw_crcs: Option<Arc<[u32]>> = self.raw_crcs.map(Into::into);
+        if let Some(crcs) = &raw_crcs {
+            seek_table.rawCRCs = crcs.as_ptr() as *mut _;
+        }
+
+        Bundle {
+            uncompressed_size: self.uncompressed_size,
+            compressed_size: self.compressed_size,
+            seek_table_size: self.seek_table_size,
+            seek_table,
+            seek_chunk_comp_lens,
+            raw_crcs,
+            chunks: self.chunks.into_iter().map(Arc::from).collect(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> BundleRef<'a, T>
+where
+    T: for<'b> TryFrom<&'b [u8]>,
+    for<'b> <T as TryFrom<&'b [u8]>>::Error: std::fmt::Debug,
+{
+    pub fn decompress(&self) -> std::io::Result<T> {
+        let data = decompress_chunks(
+            &self.chunks,
+            self.seek_table.totalRawLen as usize,
+            self.seek_table.seekChunkLen as usize,
+            self.seek_table.compressor,
+            self.chunks_independent(),
+            &DecodeThreadPhase::All,
+        )?;
+
+        T::try_from(data.as_slice())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", e)))
+    }
+}
+
+impl<'a, T> TryFrom<&'a [u8]> for BundleRef<'a, T> {
+    type Error = std::io::Error;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let mut offset = 0;
+        let uncompressed_size = read_u32(value, &mut offset)?;
+        let compressed_size = read_u32(value, &mut offset)?;
+        let seek_table_size = read_u32(value, &mut offset)?;
+
+        let mut seek_table = OodleLZ_SeekTable {
+            compressor: read_i32(value, &mut offset)?,
+            seekChunksIndependent: read_i32(value, &mut offset)?,
+            totalRawLen: read_i64(value, &mut offset)?,
+            totalCompLen: read_i64(value, &mut offset)?,
+            numSeekChunks: read_i32(value, &mut offset)?,
+            seekChunkLen: read_i32(value, &mut offset)?,
+            seekChunkCompLens: read_i64(value, &mut offset)? as *mut _,
+            rawCRCs: read_i64(value, &mut offset)? as *mut _,
+        };
+
+        let seek_chunk_bytes = read_bytes(
+            value,
+            chunk_table_len(seek_table.numSeekChunks)?,
+            &mut offset,
+        )?;
+
+        let mut seek_chunk_comp_lens: Vec<u32> = seek_chunk_bytes
+            .chunks_exact(size_of::<u32>())
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        seek_table.seekChunkCompLens = seek_chunk_comp_lens.as_mut_ptr();
+
+        let chunks = seek_chunk_comp_lens
+            .iter()
+            .map(|&size| read_bytes(value, size as usize, &mut offset))
+            .collect::<std::io::Result<_>>()?;
+
+        let raw_crcs = if offset != value.len() {
+            let raw_crcs_bytes = read_bytes(
+                value,
+                chunk_table_len(seek_table.numSeekChunks)?,
+                &mut offset,
+            )?;
+
+            let mut raw_crcs: Vec<u32> = raw_crcs_bytes
+                .chunks_exact(size_of::<u32>())
+                .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+                .collect();
+
+            seek_table.rawCRCs = raw_crcs.as_mut_ptr();
+            Some(raw_crcs)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            uncompressed_size,
+            compressed_size,
+            seek_table_size,
+            seek_table,
+            seek_chunk_comp_lens,
+            raw_crcs,
+            chunks,
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// Byte length of a table with one `u32` per seek chunk, rejecting counts that
+/// are negative or would overflow.
+fn chunk_table_len(count: i32) -> std::io::Result<usize> {
+    usize::try_from(count)
+        .ok()
+        .and_then(|count| count.checked_mul(size_of::<u32>()))
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid seek chunk count {count}"),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BundleRef;
+    use crate::Bundle;
+
+    #[test]
+    fn borrowed() {
+        let slice = include_bytes!("../resources/Shared.bundle.bin");
+        let borrowed: BundleRef<Vec<u8>> = BundleRef::try_from(slice.as_slice()).unwrap();
+        borrowed.verify().unwrap();
+        assert!(borrowed.chunks_independent());
+
+        let owned: Bundle<Vec<u8>> = Bundle::try_from(slice.as_slice()).unwrap();
+        assert_eq!(borrowed.decompress().unwrap(), owned.decompress().unwrap());
+        assert_eq!(borrowed.into_owned().to_vec(), slice);
+    }
+}
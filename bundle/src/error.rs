@@ -0,0 +1,119 @@
+use std::{fmt, io};
+
+use crate::{compressor_name, index::RecordError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleError {
+    /// Oodle couldn't build a seek table for the compressed chunks
+    SeekTable,
+    /// The seek chunk compressed lengths don't add up to `compressed_size`
+    CompressedSize { expected: u64, actual: u64 },
+    /// The chunks add up to more bytes than the header's `u32` sizes can hold
+    TooLarge(u64),
+    /// `totalRawLen` doesn't match `uncompressed_size`
+    UncompressedSize { expected: u64, actual: u64 },
+    /// The header's `seek_table_size` doesn't cover the seek table and the
+    /// per-chunk tables that were read
+    SeekTableSize { expected: u64, actual: u64 },
+    /// `numSeekChunks` doesn't match the number of chunks
+    ChunkCount { expected: usize, actual: usize },
+    /// There is a raw CRC section, but not one CRC per chunk
+    CrcCount { expected: usize, actual: usize },
+    /// A seek chunk length that isn't a power of two of at least `BLOCK_LEN`
+    ChunkLen(i64),
+    /// The chunk at this index needs the chunks before it to be decoded, as the
+    /// seek table says they aren't independent
+    DependentChunk(usize),
+    /// Oodle failed to decode a chunk. `compressor` is the one recorded in the
+    /// seek table, `detected` the one the chunk header claims. `decoded` is
+    /// what Oodle returned: the bytes it decoded out of the `expected` ones, or
+    /// `OODLELZ_FAILED` (0) if it rejected the chunk outright
+    Decompress {
+        chunk: usize,
+        compressor: i32,
+        detected: i32,
+        decoded: usize,
+        expected: usize,
+    },
+    /// Oodle failed to compress the `len` raw bytes of a chunk and returned
+    /// `code`
+    Compress { chunk: usize, len: usize, code: u32 },
+    /// [`IndexBuilder`](crate::index::IndexBuilder) was given this path more
+    /// than once, ignoring case and separators
+    DuplicatePath(String),
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SeekTable => write!(f, "failed to create the seek table"),
+            Self::CompressedSize { expected, actual } => write!(
+                f,
+                "compressed size mismatch: expected {expected} bytes, chunks add up to {actual}"
+            ),
+            Self::TooLarge(len) => write!(
+                f,
+                "chunks add up to {len} bytes, more than a bundle can hold"
+            ),
+            Self::UncompressedSize { expected, actual } => write!(
+                f,
+                "uncompressed size mismatch: expected {expected} bytes, seek table has {actual}"
+            ),
+            Self::SeekTableSize { expected, actual } => write!(
+                f,
+                "seek table size mismatch: header says {expected} bytes, seek data is {actual}"
+            ),
+            Self::ChunkCount { expected, actual } => write!(
+                f,
+                "chunk count mismatch: seek table has {expected} chunks, found {actual}"
+            ),
+            Self::CrcCount { expected, actual } => {
+                write!(f, "crc count mismatch: expected {expected}, found {actual}")
+            }
+            Self::ChunkLen(len) => write!(
+                f,
+                "invalid seek chunk length {len}, expected a power of two of at least {}",
+                oodle_safe::BLOCK_LEN
+            ),
+            Self::DependentChunk(index) => write!(
+                f,
+                "chunk {index} depends on the chunks before it and can't be decompressed on its own"
+            ),
+            Self::Decompress {
+                chunk,
+                compressor,
+                detected,
+                decoded,
+                expected,
+            } => write!(
+                f,
+                "failed to decompress chunk {chunk}: Oodle returned {decoded} of {expected} bytes, seek table compressor is {compressor} ({}), chunk header has {detected} ({})",
+                compressor_name(*compressor),
+                compressor_name(*detected)
+            ),
+            Self::Compress { chunk, len, code } => write!(
+                f,
+                "failed to compress chunk {chunk} of {len} bytes: Oodle returned {code}"
+            ),
+            Self::DuplicatePath(path) => write!(f, "{path} was added more than once"),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+impl From<BundleError> for io::Error {
+    fn from(value: BundleError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, value)
+    }
+}
+
+impl From<RecordError> for io::Error {
+    fn from(value: RecordError) -> Self {
+        let kind = match value {
+            RecordError::Truncated { .. } => io::ErrorKind::UnexpectedEof,
+            RecordError::InvalidUtf8 => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, value)
+    }
+}
@@ -0,0 +1,104 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::{collections::HashSet, path::Path};
+
+/// Glob filter over virtual paths. Patterns prefixed with `!` exclude matching
+/// paths. When there are no include patterns, every path that isn't excluded
+/// matches.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+impl Filter {
+    /// Builds a filter from a comma-separated list of globs, e.g.
+    /// `metadata/**,!art/**,!*.dds`.
+    pub fn new(patterns: &str) -> Result<Self, globset::Error> {
+        let mut include = GlobSetBuilder::new();
+        let mut exclude = GlobSetBuilder::new();
+
+        for pat in patterns
+            .split(',')
+            .map(str::trim)
+            .filter(|pat| !pat.is_empty())
+        {
+            match pat.strip_prefix('!') {
+                Some(pat) => exclude.add(Glob::new(pat)?),
+                None => include.add(Glob::new(pat)?),
+            };
+        }
+
+        Ok(Self {
+            include: include.build()?,
+            exclude: exclude.build()?,
+        })
+    }
+
+    pub fn is_match(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+
+        (self.include.is_empty() || self.include.is_match(path)) && !self.exclude.is_match(path)
+    }
+}
+
+/// Matches paths by extension alone, which is much cheaper than a glob for
+/// filters like "only `.dds` files". Extensions are compared case-insensitively.
+#[derive(Debug, Clone, Default)]
+pub struct Extensions(HashSet<String>);
+
+impl Extensions {
+    /// Builds the set from a comma-separated list, e.g. `dds,png,.datc64`.
+    pub fn new(extensions: &str) -> Self {
+        Self(
+            extensions
+                .split(',')
+                .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+                .filter(|ext| !ext.is_empty())
+                .collect(),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn is_match(&self, path: impl AsRef<Path>) -> bool {
+        path.as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.0.contains(&ext.to_lowercase()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Extensions, Filter};
+
+    #[test]
+    fn include() {
+        let filter = Filter::new("data/*.datc64").unwrap();
+
+        assert!(filter.is_match("data/mods.datc64"));
+        assert!(!filter.is_match("art/textures/foo.dds"));
+    }
+
+    #[test]
+    fn exclude() {
+        let filter = Filter::new("!art/**,!*.dds").unwrap();
+
+        assert!(filter.is_match("data/mods.datc64"));
+        assert!(!filter.is_match("art/textures/foo.png"));
+        assert!(!filter.is_match("metadata/foo.dds"));
+        assert!(Filter::default().is_match("art/textures/foo.png"));
+    }
+
+    #[test]
+    fn extensions() {
+        let extensions = Extensions::new("dds, .PNG");
+
+        assert!(extensions.is_match("art/textures/foo.dds"));
+        assert!(extensions.is_match("art/textures/foo.png"));
+        assert!(!extensions.is_match("data/mods.datc64"));
+        assert!(!extensions.is_match("data/dds"));
+    }
+}
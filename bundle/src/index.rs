@@ -1,44 +1,409 @@
-use bytemuck::{Pod, Zeroable};
-use globset::{Glob, GlobSetBuilder};
+use filetime::FileTime;
 use rayon::prelude::*;
 use std::{
-    collections::HashMap,
-    io,
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    io::{self, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
-    sync::{Arc, OnceLock},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, SystemTime},
 };
 
+mod builder;
+mod records;
+
 use super::{
+    filter::Extensions,
     util::{find_cstring, read_u32},
     Bundle,
 };
 
+pub use builder::IndexBuilder;
+pub use records::{BundleRecord, FileRecord, PathRecord, RecordError};
+
 #[cfg(feature = "tracing")]
-use tracing::{error, info, trace, warn};
+use tracing::{error, info, info_span, trace, warn};
+
+/// Options shared by the `extract` methods of [`Index`].
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    /// Also extract shadercache bundles and files
+    pub shaders: bool,
+    /// Skip files that already exist in the output with the expected size
+    pub resume: bool,
+    /// Decompress bundles one seek chunk at a time and write files as their
+    /// bytes become available, instead of decompressing whole bundles up front.
+    /// This bounds memory to roughly one chunk per bundle being extracted.
+    pub streaming: bool,
+    /// What to do with files that point at the same bytes as another file
+    pub dedup: Dedup,
+    /// Digest to compute over the contents of every extracted file
+    pub hash: ContentHash,
+    /// Write every file directly into the output directory by its file name,
+    /// see [`flatten_paths`]
+    pub flat: bool,
+    /// Modification time to give every extracted file instead of the time it
+    /// was written, so extracting the same files again is reproducible
+    pub mtime: Option<SystemTime>,
+    /// Installs layered over the one being extracted from, like patch
+    /// directories over a base install. Later ones take precedence: every
+    /// bundle is read from the last of them that has it, falling back to the
+    /// base install.
+    pub overlays: Vec<PathBuf>,
+    /// How many more times to try reading a bundle or writing a file after a
+    /// transient error, see [`retry`]
+    pub retries: u32,
+}
+
+/// How [`Index`] extraction handles files whose records share a bundle, offset
+/// and size. Duplicates are written to disk once and linked to the first path,
+/// counting as 0 bytes written. If a link can't be created the file is copied
+/// instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dedup {
+    /// Write every file, even if its bytes were already written
+    #[default]
+    Off,
+    Hardlink,
+    Symlink,
+}
+
+/// Content digest computed while extracting, see [`ExtractOptions::hash`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ContentHash {
+    #[default]
+    None,
+    Xxh3,
+    Blake3,
+}
+
+/// Digest of a file's contents. Displays as lowercase hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Digest {
+    Xxh3(u64),
+    Blake3([u8; 32]),
+}
+
+impl std::fmt::Display for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Xxh3(hash) => write!(f, "{hash:016x}"),
+            Self::Blake3(hash) => hash.iter().try_for_each(|b| write!(f, "{b:02x}")),
+        }
+    }
+}
+
+/// Incremental [`Digest`], so streamed files can be hashed chunk by chunk
+enum Hasher {
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(hash: ContentHash) -> Option<Self> {
+        match hash {
+            ContentHash::None => None,
+            ContentHash::Xxh3 => Some(Self::Xxh3(Default::default())),
+            ContentHash::Blake3 => Some(Self::Blake3(Default::default())),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Self::Xxh3(hasher) => hasher.update(bytes),
+            Self::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+        }
+    }
+
+    fn finish(&self) -> Digest {
+        match self {
+            Self::Xxh3(hasher) => Digest::Xxh3(hasher.digest()),
+            Self::Blake3(hasher) => Digest::Blake3(*hasher.finalize().as_bytes()),
+        }
+    }
+}
+
+/// A file written by [`Index::extract_with_progress`]
+#[derive(Debug, Clone, Copy)]
+pub struct Extracted<'a> {
+    /// Virtual path, relative to the output directory
+    pub path: &'a Path,
+    /// Path of the bundle the file was read from
+    pub bundle: &'a str,
+    /// Size of the file. Duplicates that were linked report it too, even
+    /// though no bytes were written for them
+    pub size: u64,
+    /// Digest of the contents, unless [`ExtractOptions::hash`] is `None`
+    pub digest: Option<Digest>,
+}
+
+/// What [`Index::extract`] got done. A bundle or file that fails doesn't stop
+/// the others from being extracted, it's listed in `failed` instead.
+#[derive(Debug, Default)]
+pub struct Extraction {
+    /// Bytes written, linked duplicates count as 0
+    pub bytes: usize,
+    pub failed: Vec<Failed>,
+}
+
+/// A bundle, or one file of it, that couldn't be extracted
+#[derive(Debug)]
+pub struct Failed {
+    /// Path of the bundle, without `.bundle.bin`
+    pub bundle: String,
+    /// Virtual path of the file, `None` if the bundle itself couldn't be read
+    pub path: Option<PathBuf>,
+    pub error: ExtractError,
+}
+
+#[derive(Debug)]
+pub enum ExtractError {
+    /// The bundle isn't in the install or any of its overlays
+    MissingBundle,
+    /// The file's record points past the end of its bundle
+    OutOfBounds {
+        offset: u32,
+        size: u32,
+        bundle_size: u32,
+    },
+    /// Reading, decompressing or writing failed, after any retries
+    Io(io::Error),
+}
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{} in ", path.display())?;
+        }
+        write!(f, "{}.bundle.bin: {}", self.bundle, self.error)
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingBundle => write!(f, "bundle doesn't exist"),
+            Self::OutOfBounds {
+                offset,
+                size,
+                bundle_size,
+            } => write!(
+                f,
+                "{size} bytes at offset {offset} are out of bounds of {bundle_size} bytes"
+            ),
+            Self::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::MissingBundle | Self::OutOfBounds { .. } => None,
+        }
+    }
+}
+
+/// Sizes and compressors read from the bundle headers of an install, see
+/// [`Index::bundle_stats`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BundleStats {
+    /// Uncompressed size of every bundle combined
+    pub uncompressed: u64,
+    /// Compressed size of every bundle combined
+    pub compressed: u64,
+    /// How many bundles use each `OodleLZ_Compressor` id, most common first
+    pub compressors: Vec<(i32, usize)>,
+}
+
+impl BundleStats {
+    /// Compressed size over uncompressed size, see [`Bundle::compression_ratio`]
+    pub fn compression_ratio(&self) -> f64 {
+        self.compressed as f64 / self.uncompressed as f64
+    }
+}
+
+/// Result of [`Index::diff`], every list sorted by path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexDiff {
+    /// Paths only in the other index
+    pub added: Vec<PathBuf>,
+    /// Paths only in this index
+    pub removed: Vec<PathBuf>,
+    /// Paths in both whose bundle, offset or size differ
+    pub changed: Vec<PathBuf>,
+}
+
+/// The file paths decoded from the path bundle, see [`Index::build_paths`]
+#[derive(Debug, Clone, Default)]
+struct PathCache {
+    /// Files with their paths, keyed by bundle index
+    bundles: HashMap<usize, Arc<[(PathBuf, FileRecord)]>>,
+    /// Decoded paths without a file record
+    orphans: Vec<String>,
+    /// Hashes of file records that no decoded path resolved to
+    unresolved: Vec<u64>,
+    /// Why the path bundle couldn't be decompressed, every file is unresolved
+    /// then. Kept as kind and message, `io::Error` can't be cloned
+    error: Option<(io::ErrorKind, String)>,
+}
+
+/// Output path, bytes written and digest of an extracted file
+type Written = (PathBuf, usize, Option<Digest>);
 
 #[derive(Debug, Clone)]
-pub struct Index<'a> {
+pub struct Index {
     /// List of paths to a Bundle.bin file
     bundles: Arc<[BundleRecord]>,
     files: Arc<[FileRecord]>,
     paths: Arc<[PathRecord]>,
     path_bundle: Bundle<Arc<[u8]>>,
-    cache: OnceLock<HashMap<usize, Arc<[(PathBuf, &'a FileRecord)]>>>,
+    cache: OnceLock<PathCache>,
+    /// Position in `files` of every path hash, kept apart from `cache` so
+    /// lookups by path don't have to decompress the path bundle
+    by_hash: OnceLock<HashMap<u64, usize>>,
 }
 
-impl<'a> Index<'a> {
+/// Serializes the index records. The path bundle is left out, it's only
+/// meaningful alongside its Oodle seek table.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Index {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("Index", 3)?;
+        state.serialize_field("bundles", &self.bundles)?;
+        state.serialize_field("files", &self.files)?;
+        state.serialize_field("paths", &self.paths)?;
+        state.end()
+    }
+}
+
+impl Index {
+    /// Reads and decompresses `Bundles2/_.index.bin` of the game install at
+    /// `game_dir`.
+    pub fn open(game_dir: impl AsRef<Path>) -> io::Result<Self> {
+        let path = game_dir.as_ref().join("Bundles2").join("_.index.bin");
+        let file = std::fs::read(&path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} doesn't exist, is this a game install?", path.display()),
+            ),
+            _ => e,
+        })?;
+
+        Bundle::<Index>::from_slice(&file)?.decompress()
+    }
+
     pub fn to_vec(self) -> Vec<u8> {
-        <Index<'a> as Into<Vec<u8>>>::into(self)
+        <Index as Into<Vec<u8>>>::into(self)
     }
 
     pub fn total_files(&self) -> usize {
         self.files.len()
     }
 
+    pub fn bundle_count(&self) -> usize {
+        self.bundles.len()
+    }
+
+    /// Looks up the bundle a [`FileRecord::bundle_idx`] refers to.
+    pub fn bundle(&self, idx: usize) -> Option<&BundleRecord> {
+        self.bundles.get(idx)
+    }
+
+    /// The compressed stream every file path is decoded from. Its directory
+    /// records use the prefix-compressed format written by [`encode_paths`].
+    pub fn path_bundle(&self) -> &Bundle<Arc<[u8]>> {
+        &self.path_bundle
+    }
+
     pub fn total_uncompressed_size(&self) -> usize {
         self.files.iter().map(|file| file.size as usize).sum()
     }
 
+    /// Pairs every bundle path with its uncompressed size.
+    pub fn bundle_sizes(&self) -> Vec<(&str, u32)> {
+        self.bundles
+            .iter()
+            .map(|bundle| (bundle.path(), bundle.uncompressed_size()))
+            .collect()
+    }
+
+    /// Sums the compressed size of every bundle under `path` by reading only
+    /// their headers. Bundles missing from the install are skipped.
+    pub fn total_compressed_size(&self, path: impl AsRef<Path>) -> io::Result<usize> {
+        Ok(self.bundle_stats(path)?.compressed as usize)
+    }
+
+    /// Compressed size over uncompressed size of every bundle under `path`
+    /// combined, see [`Bundle::compression_ratio`]. Bundles missing from the
+    /// install are skipped.
+    pub fn compression_ratio(&self, path: impl AsRef<Path>) -> io::Result<f64> {
+        Ok(self.bundle_stats(path)?.compression_ratio())
+    }
+
+    /// Counts how many bundles under `path` use each `OodleLZ_Compressor` id,
+    /// most common first. Bundles missing from the install are skipped.
+    pub fn compressor_counts(&self, path: impl AsRef<Path>) -> io::Result<Vec<(i32, usize)>> {
+        Ok(self.bundle_stats(path)?.compressors)
+    }
+
+    /// Everything the methods above report, from a single pass over the bundle
+    /// headers under `path`.
+    pub fn bundle_stats(&self, path: impl AsRef<Path>) -> io::Result<BundleStats> {
+        let mut stats = BundleStats::default();
+        let mut counts: HashMap<i32, usize> = HashMap::new();
+        for (uncompressed, compressed, compressor) in self.bundle_headers(path)? {
+            stats.uncompressed += uncompressed as u64;
+            stats.compressed += compressed as u64;
+            *counts.entry(compressor).or_default() += 1;
+        }
+
+        stats.compressors = counts.into_iter().collect();
+        stats
+            .compressors
+            .sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        Ok(stats)
+    }
+
+    /// Reads the uncompressed size, compressed size and compressor from the
+    /// header of every bundle under `path` that exists.
+    fn bundle_headers(&self, path: impl AsRef<Path>) -> io::Result<Vec<(u32, u32, i32)>> {
+        let bundles_path = path.as_ref().join("Bundles2");
+
+        self.bundles
+            .par_iter()
+            .filter_map(|record| {
+                let bundle_path = bundles_path.join(format!("{}.bundle.bin", record.path));
+                let mut file = match std::fs::File::open(&bundle_path) {
+                    Ok(file) => file,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        #[cfg(feature = "tracing")]
+                        warn!("{} doesn't exist.", bundle_path.display());
+
+                        return None;
+                    }
+                    Err(e) => return Some(Err(e)),
+                };
+
+                // uncompressed_size, compressed_size, seek_table_size, compressor
+                let mut header = [0; 16];
+                if let Err(e) = file.read_exact(&mut header) {
+                    return Some(Err(e));
+                }
+                Some(Ok((
+                    u32::from_le_bytes(header[0..4].try_into().unwrap()),
+                    u32::from_le_bytes(header[4..8].try_into().unwrap()),
+                    i32::from_le_bytes(header[12..16].try_into().unwrap()),
+                )))
+            })
+            .collect()
+    }
+
     // pub fn list_bundles(&'a self) -> Vec<&'a PathBuf> {
     //     self.iter_bundles()
     //         .map(|(_, records)| records.iter().map(|(name, _)| name).collect::<Vec<_>>())
@@ -47,52 +412,193 @@ impl<'a> Index<'a> {
     // }
 
     pub fn extract<I, T>(
-        &'a self,
+        &self,
         iter: I,
         path: impl AsRef<Path>,
         out: impl AsRef<Path>,
-        shaders: bool,
-    ) -> usize
+        options: &ExtractOptions,
+    ) -> io::Result<Extraction>
     where
         I: ParallelIterator<Item = (BundleRecord, T)> + Clone,
-        T: AsRef<[(PathBuf, &'a FileRecord)]>,
+        T: AsRef<[(PathBuf, FileRecord)]>,
     {
-        let bundles_path = path.as_ref().join("Bundles2");
-
-        assert!(bundles_path.exists());
-        assert!(bundles_path.is_dir());
-        let out = Arc::new(out.as_ref());
+        self.extract_with_progress(iter, path, out, options, |_| {})
+    }
 
-        iter.filter(|(bundle, _)| shaders || !bundle.path.contains("shadercache"))
-            .map(|(bundlerecord, files)| {
-                let mut bundlebin_path = bundlerecord.path.to_string();
-                bundlebin_path.push_str(".bundle.bin");
+    /// Same as [`Index::extract`], but calls `on_file` with every file once it
+    /// has been written to disk, or linked to its duplicate.
+    pub fn extract_with_progress<I, T>(
+        &self,
+        iter: I,
+        path: impl AsRef<Path>,
+        out: impl AsRef<Path>,
+        options: &ExtractOptions,
+        on_file: impl Fn(&Extracted) + Sync,
+    ) -> io::Result<Extraction>
+    where
+        I: ParallelIterator<Item = (BundleRecord, T)> + Clone,
+        T: AsRef<[(PathBuf, FileRecord)]>,
+    {
+        let (written, failed) = self.extract_inner(iter, path, out, options, on_file)?;
 
-                let bundle_path = bundles_path.join(bundlebin_path);
-                if !bundle_path.exists() {
-                    #[cfg(feature = "tracing")]
-                    warn!("{} doesn't exist.", bundle_path.display());
+        Ok(Extraction {
+            bytes: written.into_iter().map(|(_, bytes, _)| bytes).sum(),
+            failed,
+        })
+    }
 
-                    return 0;
-                }
+    /// Extracts every file with one of `extensions` from all bundles.
+    pub fn extract_by_extension(
+        &self,
+        path: impl AsRef<Path>,
+        out: impl AsRef<Path>,
+        extensions: &Extensions,
+        options: &ExtractOptions,
+    ) -> io::Result<Extraction> {
+        let iter = self.iter_bundles().filter_map(|(bundle, files)| {
+            let matching: Vec<_> = files
+                .iter()
+                .filter(|(path, _)| extensions.is_match(path))
+                .cloned()
+                .collect();
+
+            (!matching.is_empty()).then_some((bundle, matching))
+        });
+
+        self.extract(iter, path, out, options)
+    }
 
-                let files = files.as_ref();
+    /// Extracts every file whose virtual path starts with `prefix`, e.g.
+    /// `art/2ditems/`, ignoring case. A plain prefix check, so unlike a glob
+    /// `art/2ditems` also matches `art/2ditemsfoo/`.
+    pub fn extract_under(
+        &self,
+        path: impl AsRef<Path>,
+        out: impl AsRef<Path>,
+        prefix: &str,
+        options: &ExtractOptions,
+    ) -> io::Result<Extraction> {
+        let iter = self.iter_bundles().filter_map(|(bundle, files)| {
+            let matching: Vec<_> = files
+                .iter()
+                .filter(|(path, _)| is_under(path, prefix))
+                .cloned()
+                .collect();
+
+            (!matching.is_empty()).then_some((bundle, matching))
+        });
+
+        self.extract(iter, path, out, options)
+    }
 
-                let file = std::fs::read(&bundle_path).unwrap();
-                let bundle: Bundle<Vec<u8>> = Bundle::from_slice(&file).unwrap();
+    /// Same as [`Index::extract`], but returns the output path of every file
+    /// that was written instead of the total byte count, along with the
+    /// failures.
+    pub fn extract_files<I, T>(
+        &self,
+        iter: I,
+        path: impl AsRef<Path>,
+        out: impl AsRef<Path>,
+        options: &ExtractOptions,
+    ) -> io::Result<(Vec<PathBuf>, Vec<Failed>)>
+    where
+        I: ParallelIterator<Item = (BundleRecord, T)> + Clone,
+        T: AsRef<[(PathBuf, FileRecord)]>,
+    {
+        let (written, failed) = self.extract_inner(iter, path, out, options, |_| {})?;
 
-                #[cfg(feature = "tracing")]
-                info!(
-                    bundle = bundlerecord.path.as_ref(),
-                    "Decompressing {}.bundle.bin", bundlerecord.path,
-                );
+        Ok((
+            written.into_iter().map(|(path, _, _)| path).collect(),
+            failed,
+        ))
+    }
 
-                let data = bundle.decompress().unwrap();
+    /// Shared extraction loop. With `resume`, files that already exist in `out`
+    /// with the expected size are skipped, and bundles with nothing left to
+    /// write aren't read at all. Fails before anything is written if `path` or
+    /// one of the overlays has no `Bundles2` directory, or if the path bundle
+    /// is corrupt, see [`Index::check_paths`].
+    fn extract_inner<I, T>(
+        &self,
+        iter: I,
+        path: impl AsRef<Path>,
+        out: impl AsRef<Path>,
+        options: &ExtractOptions,
+        on_file: impl Fn(&Extracted) + Sync,
+    ) -> io::Result<(Vec<Written>, Vec<Failed>)>
+    where
+        I: ParallelIterator<Item = (BundleRecord, T)> + Clone,
+        T: AsRef<[(PathBuf, FileRecord)]>,
+    {
+        let ExtractOptions {
+            shaders,
+            resume,
+            streaming,
+            dedup,
+            hash,
+            flat,
+            mtime,
+            ref overlays,
+            retries,
+        } = *options;
+        self.check_paths()?;
+        let installs = overlays.iter().rev().map(PathBuf::as_path);
+        let roots = installs
+            .chain([path.as_ref()])
+            .map(|install| {
+                let root = install.join("Bundles2");
+                match root.is_dir() {
+                    true => Ok(root),
+                    false => Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!(
+                            "expected Bundles2 under {}; is this a PoE2 install directory?",
+                            install.display()
+                        ),
+                    )),
+                }
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let out = Arc::new(out.as_ref());
 
-                let out_dir = out.clone();
+        // Collisions are resolved across all bundles before any are extracted
+        let names = match flat {
+            true => flatten_paths(
+                iter.clone()
+                    .flat_map_iter(|(_, files)| {
+                        files
+                            .as_ref()
+                            .iter()
+                            .map(|(path, _)| path.clone())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect(),
+            ),
+            false => HashMap::new(),
+        };
+        let dest = |path: &Path| out.join(names.get(path).map_or(path, PathBuf::as_path));
+
+        let failed = Mutex::new(vec![]);
+        let fail = |bundle: &str, path: Option<&Path>, error: ExtractError| {
+            let failed_file = Failed {
+                bundle: bundle.to_string(),
+                path: path.map(Path::to_path_buf),
+                error,
+            };
+            #[cfg(feature = "tracing")]
+            // Missing bundles were already warned about
+            if !matches!(failed_file.error, ExtractError::MissingBundle) {
+                error!("Failed to extract {failed_file}");
+            }
+            failed.lock().unwrap().push(failed_file);
+        };
 
-                let bytes: Vec<_> = files
-                    .par_iter()
+        let written = iter
+            .filter(|(bundle, _)| shaders || !bundle.path.contains("shadercache"))
+            .map(|(bundlerecord, files)| {
+                let files: Vec<_> = files
+                    .as_ref()
+                    .iter()
                     .filter(|(path, _)| {
                         let is_shader = path
                             .components()
@@ -101,51 +607,212 @@ impl<'a> Index<'a> {
 
                         shaders || !is_shader
                     })
-                    .map(|(path, info)| -> usize {
-                        let start = info.offset as usize;
-                        let end = start + info.size as usize;
-                        let mut slice = &data[start..end];
+                    .collect();
 
-                        let file_path = out_dir.clone().join(path);
-                        let parent = file_path.parent().unwrap();
+                let pending: Vec<_> = files
+                    .iter()
+                    .copied()
+                    .filter(|(path, info)| {
+                        !resume
+                            || !std::fs::metadata(dest(path))
+                                .is_ok_and(|meta| meta.len() == info.size as u64)
+                    })
+                    .collect();
 
-                        if !parent.exists() {
-                            std::fs::create_dir_all(parent).unwrap();
-                        }
+                if pending.is_empty() {
+                    #[cfg(feature = "tracing")]
+                    trace!(
+                        done = files.len() as u64,
+                        "Skipped {}.bundle.bin",
+                        bundlerecord.path
+                    );
+
+                    return vec![];
+                }
+
+                let mut bundlebin_path = bundlerecord.path.to_string();
+                bundlebin_path.push_str(".bundle.bin");
+
+                let Some(bundle_path) = find_bundle(&roots, &bundlebin_path) else {
+                    #[cfg(feature = "tracing")]
+                    warn!(
+                        missing = bundlerecord.path.as_ref(),
+                        "{bundlebin_path} doesn't exist."
+                    );
+                    fail(&bundlerecord.path, None, ExtractError::MissingBundle);
+
+                    return vec![];
+                };
 
-                        let mut file = std::fs::File::create(&file_path).unwrap();
-                        let bytes = std::io::copy(&mut slice, &mut file).unwrap();
-                        assert_eq!(bytes, info.size as u64);
-                        bytes as usize
+                // Not entered, rayon can steal other bundles' work onto this
+                // thread while it waits on the files below
+                #[cfg(feature = "tracing")]
+                let span = info_span!(
+                    "extract_bundle",
+                    bundle = bundlerecord.path.as_ref(),
+                    elapsed_ms = tracing::field::Empty
+                );
+                #[cfg(feature = "tracing")]
+                let started = std::time::Instant::now();
+
+                let bundle = match retry(retries, || load_bundle(&bundle_path)) {
+                    Ok(bundle) => bundle,
+                    Err(e) => {
+                        fail(&bundlerecord.path, None, ExtractError::Io(e));
+                        return vec![];
+                    }
+                };
+                let fail_file = |path: Option<&Path>, e: io::Error| {
+                    fail(&bundlerecord.path, path, ExtractError::Io(e))
+                };
+                let report = |path: &Path, size: u64, digest: Option<Digest>| {
+                    on_file(&Extracted {
+                        path,
+                        bundle: &bundlerecord.path,
+                        size,
+                        digest,
                     })
-                    .collect();
+                };
 
                 #[cfg(feature = "tracing")]
-                trace!(
-                    done = bytes.len() as u64,
-                    "Done {}.bundle.bin",
-                    bundlerecord.path
+                info!(
+                    bundle = bundlerecord.path.as_ref(),
+                    "Decompressing {}.bundle.bin", bundlerecord.path,
                 );
 
-                bytes.iter().sum()
+                // A corrupt record shouldn't take the rest of the bundle down with it
+                let bundle_size = bundle.uncompressed_size();
+                let (pending, out_of_bounds): (Vec<_>, Vec<_>) =
+                    pending.into_iter().partition(|(_, info)| {
+                        info.offset as u64 + info.size as u64 <= bundle_size as u64
+                    });
+                for (path, info) in out_of_bounds {
+                    let error = ExtractError::OutOfBounds {
+                        offset: info.offset,
+                        size: info.size,
+                        bundle_size,
+                    };
+                    fail(&bundlerecord.path, Some(path), error);
+                }
+
+                let (pending, duplicates) = match dedup {
+                    Dedup::Off => (pending, vec![]),
+                    _ => split_duplicates(pending),
+                };
+
+                // Dependent chunks can't be decoded one at a time
+                let mut written = if streaming && bundle.chunks_independent() {
+                    write_streamed(&bundle, pending, &dest, hash, retries, &report, &fail_file)
+                } else {
+                    // Already parallel across bundles
+                    let data = match bundle.decompress_sequential() {
+                        Ok(data) => data,
+                        Err(e) => {
+                            fail_file(None, e);
+                            return vec![];
+                        }
+                    };
+
+                    pending
+                        .par_iter()
+                        .filter_map(|(path, info)| {
+                            let start = info.offset as usize;
+                            let end = start + info.size as usize;
+                            let slice = &data[start..end];
+                            let digest = Hasher::new(hash).map(|mut hasher| {
+                                hasher.update(slice);
+                                hasher.finish()
+                            });
+
+                            let file_path = dest(path);
+                            let parent = file_path.parent().unwrap();
+
+                            // Every attempt starts over from the beginning of the file
+                            let bytes = std::fs::create_dir_all(parent).and_then(|_| {
+                                retry(retries, || {
+                                    let mut file = std::fs::File::create(&file_path)?;
+                                    std::io::copy(&mut &slice[..], &mut file)
+                                })
+                            });
+                            let bytes = match bytes {
+                                Ok(bytes) => bytes,
+                                Err(e) => {
+                                    fail_file(Some(path), e);
+                                    return None;
+                                }
+                            };
+                            report(path, bytes, digest);
+                            Some((file_path, bytes as usize, digest))
+                        })
+                        .collect()
+                };
+
+                // Duplicates have the same contents as their source, reuse its digest
+                let digests: HashMap<PathBuf, Digest> = match hash {
+                    ContentHash::None => HashMap::new(),
+                    _ => written
+                        .iter()
+                        .filter_map(|(path, _, digest)| Some((path.clone(), (*digest)?)))
+                        .collect(),
+                };
+                written.par_extend(duplicates.par_iter().filter_map(|((path, info), source)| {
+                    let file_path = dest(path);
+                    let source = dest(source);
+                    if let Err(e) = link(dedup, &source, &file_path) {
+                        fail_file(Some(path), e);
+                        return None;
+                    }
+                    let digest = digests.get(&source).copied();
+                    report(path, info.size as u64, digest);
+                    Some((file_path, 0, digest))
+                }));
+
+                // Links share the source's time, or resolve to it
+                if let Some(mtime) = mtime.map(FileTime::from_system_time) {
+                    // Failures are reported by virtual path, like the ones above
+                    let paths: HashMap<PathBuf, &Path> = files
+                        .iter()
+                        .map(|(path, _)| (dest(path), path.as_path()))
+                        .collect();
+                    written.par_iter().for_each(|(file_path, _, _)| {
+                        if let Err(e) = filetime::set_file_mtime(file_path, mtime) {
+                            fail_file(paths.get(file_path).copied(), e);
+                        }
+                    });
+                }
+
+                #[cfg(feature = "tracing")]
+                {
+                    let elapsed = started.elapsed();
+                    span.record("elapsed_ms", elapsed.as_millis() as u64);
+                    trace!(
+                        parent: &span,
+                        done = files.len() as u64,
+                        "Done {}.bundle.bin in {elapsed:?}",
+                        bundlerecord.path
+                    );
+                }
+
+                written
             })
-            .sum()
+            .flatten()
+            .collect();
+
+        Ok((written, failed.into_inner().unwrap()))
     }
 
-    // pub fn bundle_info_by_idx(
-    //     &'a self,
-    //     idx: usize,
-    // ) -> Option<(&'a BundleRecord, Arc<&'a [(PathBuf, &'a FileRecord)]>)> {
-    //     self.build_paths()
-    //         .get(&idx)
-    //         .map(|info| (&self.bundles[idx], info))
-    // }
+    /// Every file with its resolved path, in no particular order.
+    pub fn iter_files(&self) -> impl Iterator<Item = (&Path, &FileRecord)> {
+        self.build_paths()
+            .bundles
+            .values()
+            .flat_map(|files| files.iter().map(|(path, record)| (path.as_path(), record)))
+    }
 
     pub fn iter_bundles(
-        &'a self,
-    ) -> impl ParallelIterator<Item = (BundleRecord, &'a Arc<[(PathBuf, &'a FileRecord)]>)> + Clone
-    {
-        let paths = self.build_paths();
+        &self,
+    ) -> impl ParallelIterator<Item = (BundleRecord, &Arc<[(PathBuf, FileRecord)]>)> + Clone {
+        let paths = &self.build_paths().bundles;
         let bundles = &self.bundles;
 
         paths
@@ -153,68 +820,548 @@ impl<'a> Index<'a> {
             .map(|(&idx, info)| (bundles[idx].clone(), info))
     }
 
-    fn build_paths(&'a self) -> &'a HashMap<usize, Arc<[(PathBuf, &'a FileRecord)]>> {
-        //TODO check back later if added mutable support, cache might bite us
+    fn build_paths(&self) -> &PathCache {
+        // Anything that mutates files or bundles has to reset the caches, see `replace_file`
 
         self.cache.get_or_init(|| {
-            let map: HashMap<_, _> = self.files.iter().map(|file| (file.hash, file)).collect();
-            let bytes = &self.path_bundle.decompress().unwrap();
-
-            let mut paths: HashMap<usize, Vec<(PathBuf, &FileRecord)>> = HashMap::new();
-
-            for path in self.paths.iter() {
-                let slice = &bytes[path.offset as usize..(path.offset + path.size) as usize];
-                let mut offset = 0;
-                let mut path_slice: Vec<String> = vec![];
-                let mut building = read_u32(slice, &mut offset) == 0;
-
-                while offset < path.size as usize - 4 {
-                    let mut index = read_u32(slice, &mut offset);
-                    if index == 0 {
-                        building = !building;
-                        if building {
-                            path_slice.clear();
-                        }
-                    } else {
-                        index -= 1;
-                        let mut string = find_cstring(slice, &mut offset).unwrap();
-                        if (index as usize) < path_slice.len() {
-                            let mut prev = path_slice[index as usize].clone();
-                            prev.push_str(string.as_str());
-                            string = prev;
-                        }
-                        path_slice.push(string.clone());
-                        if !building {
-                            let hash = murmurhash64::murmur_hash64a(string.as_bytes(), 0x1337b33f);
-                            if let Some(fr) = map.get(&hash) {
-                                paths
-                                    .entry(fr.bundle_idx as usize)
-                                    .or_default()
-                                    .push((string.into(), fr));
-                            } else {
-                                #[cfg(feature = "tracing")]
-                                error!("Hash not found: {}", string);
-                            };
-                        }
+            let map = self.files_by_hash();
+            let (bytes, error) = match self.path_bundle.decompress() {
+                Ok(bytes) => (bytes, None),
+                Err(e) => {
+                    #[cfg(feature = "tracing")]
+                    error!("Couldn't decompress the path bundle: {e}");
+                    (Arc::from([]), Some((e.kind(), e.to_string())))
+                }
+            };
+
+            let mut paths: HashMap<usize, Vec<(PathBuf, FileRecord)>> = HashMap::new();
+            let mut orphans = vec![];
+            let mut resolved = vec![false; self.files.len()];
+
+            for record in self.paths.iter().filter(|_| error.is_none()) {
+                let strings = match decode_paths(&bytes, record) {
+                    Ok(strings) => strings,
+                    Err(_e) => {
+                        #[cfg(feature = "tracing")]
+                        error!("Invalid path record: {_e}");
+                        continue;
                     }
+                };
+                for string in strings {
+                    let hash = murmurhash64::murmur_hash64a(string.as_bytes(), 0x1337b33f);
+                    if let Some(&i) = map.get(&hash) {
+                        resolved[i] = true;
+                        let fr = self.files[i];
+                        paths
+                            .entry(fr.bundle_idx as usize)
+                            .or_default()
+                            .push((string.into(), fr));
+                    } else {
+                        #[cfg(feature = "tracing")]
+                        error!("No file for path: {}", string);
+                        orphans.push(string);
+                    };
                 }
             }
-            paths.into_iter().map(|(k, v)| (k, Arc::from(v))).collect()
+
+            let unresolved = self
+                .files
+                .iter()
+                .zip(&resolved)
+                .filter(|(_, &resolved)| !resolved)
+                .map(|(file, _)| file.hash)
+                .collect();
+
+            PathCache {
+                bundles: paths.into_iter().map(|(k, v)| (k, Arc::from(v))).collect(),
+                orphans,
+                unresolved,
+                error,
+            }
         })
     }
 
-    pub fn files(&self) {
-        for file in self.files.as_ref() {
-            let hash = file.hash;
-            println!(
-                "File: {} in Directory: {}",
-                hash, self.bundles[file.bundle_idx as usize].path
-            );
+    /// Fails if the path bundle can't be decompressed, in which case no file
+    /// has a path and [`Index::iter_files`] and [`Index::iter_bundles`] are
+    /// empty. Builds the path cache.
+    pub fn check_paths(&self) -> io::Result<()> {
+        match &self.build_paths().error {
+            Some((kind, message)) => Err(io::Error::new(
+                *kind,
+                format!("Couldn't decompress the path bundle: {message}"),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Path hashes of files that no path in the path bundle resolved to, so
+    /// they're never extracted. Builds the path cache.
+    pub fn unresolved_files(&self) -> Vec<u64> {
+        self.build_paths().unresolved.clone()
+    }
+
+    /// Paths in the path bundle whose hash doesn't match any file. Builds the
+    /// path cache.
+    pub fn orphan_paths(&self) -> Vec<String> {
+        self.build_paths().orphans.clone()
+    }
+
+    fn files_by_hash(&self) -> &HashMap<u64, usize> {
+        self.by_hash.get_or_init(|| {
+            self.files
+                .iter()
+                .enumerate()
+                .map(|(i, file)| (file.hash, i))
+                .collect()
+        })
+    }
+
+    /// Compares the files of two indices, e.g. from consecutive patches, by
+    /// path. A file changed if its bundle, offset or size moved. Builds the path
+    /// cache of both indices.
+    pub fn diff(&self, other: &Index) -> IndexDiff {
+        let ours = self.files_by_hash();
+        let theirs = other.files_by_hash();
+        let mut diff = IndexDiff::default();
+
+        for (path, file) in self.iter_files() {
+            match theirs.get(&{ file.hash }) {
+                None => diff.removed.push(path.to_path_buf()),
+                Some(&i) => {
+                    let new = &other.files[i];
+                    let moved = self.bundles[file.bundle_idx()].path
+                        != other.bundles[new.bundle_idx()].path
+                        || { file.offset } != { new.offset }
+                        || { file.size } != { new.size };
+                    if moved {
+                        diff.changed.push(path.to_path_buf());
+                    }
+                }
+            }
+        }
+        diff.added = other
+            .iter_files()
+            .filter(|(_, file)| !ours.contains_key(&{ file.hash }))
+            .map(|(path, _)| path.to_path_buf())
+            .collect();
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort();
+        diff
+    }
+
+    /// Looks up a file by its virtual path without building the path cache.
+    pub fn file_by_path(&self, file: &str) -> Option<&FileRecord> {
+        self.files_by_hash()
+            .get(&hash_path(file))
+            .map(|&i| &self.files[i])
+    }
+
+    /// Whether `file` is in the index, ignoring case. Doesn't build the path
+    /// cache.
+    pub fn contains(&self, file: &str) -> bool {
+        self.file_by_path(file).is_some()
+    }
+
+    /// Path of the bundle holding `file`, ignoring case. This is the one
+    /// `Bundles2/{path}.bundle.bin` to fetch to read the file. Doesn't build the
+    /// path cache.
+    pub fn bundle_for_file(&self, file: &str) -> Option<&str> {
+        let record = self.file_by_path(file)?;
+        self.bundle(record.bundle_idx()).map(BundleRecord::path)
+    }
+
+    /// Reads a single file by its virtual path (e.g. `data/mods.datc64`) from
+    /// the install at `path`, decompressing only the bundle that contains it.
+    /// Doesn't build the path cache.
+    pub fn read_file(&self, path: impl AsRef<Path>, file: &str) -> io::Result<Vec<u8>> {
+        self.read_file_layered(&[path], file)
+    }
+
+    /// Same as [`Index::read_file`] for layered installs, like a base install
+    /// and then patch directories. The bundle is read from the last of
+    /// `installs` that has it, as with [`ExtractOptions::overlays`].
+    pub fn read_file_layered(
+        &self,
+        installs: &[impl AsRef<Path>],
+        file: &str,
+    ) -> io::Result<Vec<u8>> {
+        let record = self.file_by_path(file).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{file} is not in the index"),
+            )
+        })?;
+
+        let bundle = &self.bundles[record.bundle_idx as usize];
+        let bundlebin_path = format!("{}.bundle.bin", bundle.path);
+        let roots: Vec<PathBuf> = installs
+            .iter()
+            .rev()
+            .map(|install| install.as_ref().join("Bundles2"))
+            .collect();
+        let bundle_path = find_bundle(&roots, &bundlebin_path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{bundlebin_path}, which holds {file}, doesn't exist"),
+            )
+        })?;
+        let bundle_bin = load_bundle(&bundle_path)?;
+
+        bundle_bin
+            .decompress_range(record.offset as usize, record.size as usize)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::InvalidInput => io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{file} is out of bounds of {}.bundle.bin", bundle.path),
+                ),
+                _ => e,
+            })
+    }
+
+    /// Replaces the contents of `file` and re-compresses the bundle that holds
+    /// it, which is read from the install at `path`. The offsets of the files
+    /// after it in the same bundle are shifted to match, so the updated index
+    /// and the returned bundle need to be written back together.
+    pub fn replace_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        file: &str,
+        new_bytes: &[u8],
+    ) -> io::Result<ModifiedBundle> {
+        let target = *self.file_by_path(file).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{file} is not in the index"),
+            )
+        })?;
+
+        let bundle_idx = target.bundle_idx as usize;
+        let record = &self.bundles[bundle_idx];
+        let bundle_path = path
+            .as_ref()
+            .join("Bundles2")
+            .join(format!("{}.bundle.bin", record.path));
+        let data = load_bundle(&bundle_path)?.decompress()?;
+
+        let start = target.offset as usize;
+        let end = start + target.size as usize;
+        if end > data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{file} is out of bounds of {}.bundle.bin", record.path),
+            ));
+        }
+
+        let mut replaced = Vec::with_capacity(data.len() - (end - start) + new_bytes.len());
+        replaced.extend_from_slice(&data[..start]);
+        replaced.extend_from_slice(new_bytes);
+        replaced.extend_from_slice(&data[end..]);
+
+        let uncompressed_size = u32::try_from(replaced.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}.bundle.bin would exceed 4 GiB", record.path),
+            )
+        })?;
+        let new_size = new_bytes.len() as u32;
+
+        let mut files = self.files.to_vec();
+        for record in files
+            .iter_mut()
+            .filter(|record| record.bundle_idx as usize == bundle_idx)
+        {
+            if record.offset == target.offset && record.size == target.size {
+                // Every path sharing the old bytes now points at the new ones
+                record.size = new_size;
+            } else if record.offset as usize >= end {
+                record.offset = record.offset - target.size + new_size;
+            }
+        }
+
+        let path: Arc<str> = record.path.clone();
+        let bundle = Bundle::new(replaced)?;
+
+        let mut bundles = self.bundles.to_vec();
+        bundles[bundle_idx].uncompressed_size = uncompressed_size;
+
+        self.files = files.into();
+        self.bundles = bundles.into();
+        self.cache = OnceLock::new();
+        self.by_hash = OnceLock::new();
+
+        Ok(ModifiedBundle { path, bundle })
+    }
+
+    /// Writes a `{hash}\t{path}` line for every file, sorted by hash, with
+    /// `UNKNOWN` as the path of hashes no decoded path resolves to. Hashes are
+    /// the 16 hex digits of [`FileRecord`]'s murmurhash64.
+    pub fn write_hashes<W: Write>(&self, mut out: W) -> io::Result<()> {
+        self.check_paths()?;
+        let paths = self.build_paths();
+        let mut hashes: Vec<(u64, Option<&Path>)> = paths
+            .bundles
+            .values()
+            .flat_map(|files| files.iter())
+            .map(|(path, info)| (info.hash, Some(path.as_path())))
+            .chain(paths.unresolved.iter().map(|&hash| (hash, None)))
+            .collect();
+        hashes.sort_unstable();
+
+        for (hash, path) in hashes {
+            match path {
+                Some(path) => writeln!(out, "{hash:016x}\t{}", path.display())?,
+                None => writeln!(out, "{hash:016x}\tUNKNOWN")?,
+            }
+        }
+
+        out.flush()
+    }
+
+    /// Writes a JSON array with one `{ path, hash, bundle, offset, size }` object
+    /// per file, sorted by path. `bundle` is the bundle's path and `hash` is a
+    /// 16 digit hex string, as it doesn't fit in a JavaScript number.
+    pub fn write_manifest<W: Write>(&self, mut out: W) -> io::Result<()> {
+        self.check_paths()?;
+        let mut files: Vec<_> = self
+            .build_paths()
+            .bundles
+            .values()
+            .flat_map(|files| files.iter())
+            .collect();
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        out.write_all(b"[")?;
+        for (i, (path, info)) in files.into_iter().enumerate() {
+            if i > 0 {
+                out.write_all(b",")?;
+            }
+            out.write_all(b"\n  {\"path\":")?;
+            write_json_str(&mut out, &path.to_string_lossy())?;
+            write!(out, ",\"hash\":\"{:016x}\",\"bundle\":", { info.hash })?;
+            write_json_str(&mut out, &self.bundles[info.bundle_idx as usize].path)?;
+            write!(out, ",\"offset\":{},\"size\":{}}}", { info.offset }, {
+                info.size
+            })?;
+        }
+        out.write_all(b"\n]\n")?;
+
+        out.flush()
+    }
+
+    /// Pairs the path of the bundle holding every file with the file's path
+    /// hash, in index order. Doesn't need the path bundle.
+    pub fn files(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.files
+            .iter()
+            .map(|file| (self.bundles[file.bundle_idx as usize].path(), file.hash))
+    }
+}
+
+/// Writes a JSON array with one `{ path, bundle, size }` object per extracted
+/// file, in the order given. Files with a digest also get a hex `hash`.
+pub fn write_extracted<'a, W: Write>(
+    entries: impl IntoIterator<Item = Extracted<'a>>,
+    mut out: W,
+) -> io::Result<()> {
+    out.write_all(b"[")?;
+    for (i, entry) in entries.into_iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        out.write_all(b"\n  {\"path\":")?;
+        write_json_str(&mut out, &entry.path.to_string_lossy())?;
+        out.write_all(b",\"bundle\":")?;
+        write_json_str(&mut out, entry.bundle)?;
+        write!(out, ",\"size\":{}", entry.size)?;
+        if let Some(digest) = entry.digest {
+            write!(out, ",\"hash\":\"{digest}\"")?;
+        }
+        out.write_all(b"}")?;
+    }
+    out.write_all(b"\n]\n")?;
+
+    out.flush()
+}
+
+fn write_json_str(out: &mut impl Write, value: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{c}")?,
+        }
+    }
+    out.write_all(b"\"")
+}
+
+/// Decodes the file paths of a single directory record, the reverse of
+/// [`encode_paths`].
+///
+/// Records alternate between building a list of base strings and emitting
+/// files, toggled by a `0`. Every other entry is a 1-based reference to an
+/// earlier base (ignored when out of range) followed by a null-terminated
+/// string that is appended to it.
+fn decode_paths(bytes: &[u8], record: &PathRecord) -> io::Result<Vec<String>> {
+    let start = record.offset as usize;
+    let slice = bytes
+        .get(start..start + record.size as usize)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated path bundle"))?;
+    let mut offset = 0;
+    let mut path_slice: Vec<String> = vec![];
+    let mut files = vec![];
+    let mut building = read_u32(slice, &mut offset)? == 0;
+
+    while offset < slice.len().saturating_sub(4) {
+        let mut index = read_u32(slice, &mut offset)?;
+        if index == 0 {
+            building = !building;
+            if building {
+                path_slice.clear();
+            }
+        } else {
+            index -= 1;
+            let mut string = find_cstring(slice, &mut offset).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Unterminated path string")
+            })?;
+            if (index as usize) < path_slice.len() {
+                let mut prev = path_slice[index as usize].clone();
+                prev.push_str(string.as_str());
+                string = prev;
+            }
+            path_slice.push(string.clone());
+            if !building {
+                files.push(string);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Encodes file paths into the format read by [`decode_paths`], returning the
+/// directory records and the path bundle data they point into.
+///
+/// Paths are grouped into one record per top-level directory. Each record first
+/// lists every directory in the group as a base that references its parent's
+/// base, then every file as a reference to its directory's base plus its name.
+pub fn encode_paths<P: AsRef<Path>>(paths: &[(P, FileRecord)]) -> (Vec<PathRecord>, Vec<u8>) {
+    let mut groups: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (path, _) in paths {
+        let path = path.as_ref().to_string_lossy().replace('\\', "/");
+        let top = path.split_once('/').map_or("", |(top, _)| top).to_string();
+        groups.entry(top).or_default().insert(path);
+    }
+
+    let mut records = Vec::with_capacity(groups.len());
+    let mut data = vec![];
+
+    for (top, files) in groups {
+        let offset = data.len();
+
+        // Parents sort before their children, so every base can reference one
+        // that was already written
+        let dirs: BTreeSet<&str> = files
+            .iter()
+            .flat_map(|file| file.match_indices('/').map(|(i, _)| &file[..=i]))
+            .chain(files.iter().filter(|file| !file.contains('/')).map(|_| ""))
+            .collect();
+        let bases: HashMap<&str, u32> = dirs.iter().zip(1..).map(|(&dir, i)| (dir, i)).collect();
+
+        data.extend_from_slice(&0u32.to_le_bytes());
+        for (i, dir) in dirs.iter().enumerate() {
+            let parent = dir[..dir.len().saturating_sub(1)]
+                .rfind('/')
+                .map(|end| &dir[..=end]);
+
+            match parent.and_then(|parent| Some((parent, bases.get(parent)?))) {
+                Some((parent, &index)) => {
+                    data.extend_from_slice(&index.to_le_bytes());
+                    data.extend_from_slice(&dir.as_bytes()[parent.len()..]);
+                }
+                None => {
+                    // One past the bases built so far doesn't reference anything
+                    data.extend_from_slice(&(i as u32 + 1).to_le_bytes());
+                    data.extend_from_slice(dir.as_bytes());
+                }
+            }
+            data.push(0);
+        }
+
+        data.extend_from_slice(&0u32.to_le_bytes());
+        for file in &files {
+            let dir = file.rfind('/').map_or("", |end| &file[..=end]);
+            data.extend_from_slice(&bases[dir].to_le_bytes());
+            data.extend_from_slice(&file.as_bytes()[dir.len()..]);
+            data.push(0);
+        }
+
+        let size = (data.len() - offset) as u32;
+        records.push(PathRecord {
+            hash: hash_path(&top),
+            offset: offset as u32,
+            size,
+            recursive_length: size,
+        });
+    }
+
+    (records, data)
+}
+
+/// Hashes a virtual path the way the index does. Paths are lowercased first,
+/// so lookups are case-insensitive.
+fn hash_path(path: &str) -> u64 {
+    murmurhash64::murmur_hash64a(path.to_lowercase().as_bytes(), 0x1337b33f)
+}
+
+/// Loads the bundle at `path`, or concatenates its numbered parts if the
+/// install split it up, see [`read_parts`].
+fn load_bundle(path: &Path) -> io::Result<Bundle<Vec<u8>>> {
+    if !path.exists() {
+        if let Some(file) = read_parts(path)? {
+            return Bundle::from_slice(&file);
         }
     }
+
+    // SAFETY: bundles are only ever read, and the game install isn't
+    // expected to change underneath us while extracting.
+    #[cfg(feature = "mmap")]
+    let file = unsafe { memmap2::Mmap::map(&std::fs::File::open(path)?)? };
+    #[cfg(not(feature = "mmap"))]
+    let file = std::fs::read(path)?;
+
+    Bundle::from_slice(&file)
+}
+
+/// Path of the `part`th piece of a split bundle, `{path}.part{part}`.
+fn part_path(path: &Path, part: usize) -> PathBuf {
+    let mut part_path = path.as_os_str().to_owned();
+    part_path.push(format!(".part{part}"));
+    part_path.into()
+}
+
+/// Reads `{path}.part0`, `{path}.part1`, ... up to the first missing part into
+/// one buffer. `None` if there isn't a `.part0`.
+fn read_parts(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    let mut part = 0;
+
+    while let Some(bytes) = match std::fs::read(part_path(path, part)) {
+        Ok(bytes) => Some(bytes),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    } {
+        data.extend_from_slice(&bytes);
+        part += 1;
+    }
+
+    Ok((part > 0).then_some(data))
 }
 
-impl From<Index<'_>> for Vec<u8> {
+impl From<Index> for Vec<u8> {
     fn from(val: Index) -> Self {
         let mut data = Vec::new();
 
@@ -242,7 +1389,7 @@ impl From<Index<'_>> for Vec<u8> {
         data
     }
 }
-impl From<Index<'_>> for Arc<[u8]> {
+impl From<Index> for Arc<[u8]> {
     fn from(val: Index) -> Self {
         let mut data = Vec::new();
 
@@ -271,7 +1418,7 @@ impl From<Index<'_>> for Arc<[u8]> {
     }
 }
 
-impl From<&Index<'_>> for Vec<u8> {
+impl From<&Index> for Vec<u8> {
     fn from(val: &Index) -> Vec<u8> {
         let mut data = Vec::new();
 
@@ -299,7 +1446,7 @@ impl From<&Index<'_>> for Vec<u8> {
         data
     }
 }
-impl From<&Index<'_>> for Arc<[u8]> {
+impl From<&Index> for Arc<[u8]> {
     fn from(val: &Index) -> Self {
         let mut data = Vec::new();
 
@@ -328,283 +1475,765 @@ impl From<&Index<'_>> for Arc<[u8]> {
     }
 }
 
-impl TryFrom<&[u8]> for Index<'_> {
+impl TryFrom<&[u8]> for Index {
     type Error = std::io::Error;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let bundle_count = u32::from_le_bytes(value[0..4].try_into().unwrap());
-        let mut bundles = Vec::with_capacity(bundle_count as usize);
+        let mut offset = 0;
 
-        let mut offset = 4;
+        let bundle_count = read_count(value, &mut offset)?;
+        // Smallest record is an empty path, don't trust the count for the allocation
+        let mut bundles = Vec::with_capacity(bundle_count.min(value.len() / 8));
 
         for _ in 0..bundle_count {
-            let record = BundleRecord::try_from(&value[offset..])?;
+            let record = BundleRecord::try_from(value.get(offset..).unwrap_or_default())?;
             offset += record.size(); // path_length, path, size
 
             bundles.push(record);
         }
 
-        let file_count = u32::from_le_bytes(value[offset..offset + 4].try_into().unwrap());
-        offset += 4;
-        let mut files = Vec::with_capacity(file_count as usize);
+        let file_count = read_count(value, &mut offset)?;
+        let file_record_size = std::mem::size_of::<FileRecord>();
+        let mut files = Vec::with_capacity(file_count.min(value.len() / file_record_size));
 
         for _ in 0..file_count {
-            let file_record_size = std::mem::size_of::<FileRecord>();
-            let record = FileRecord::try_from(&value[offset..offset + file_record_size])?;
-            offset += file_record_size;
+            let record = FileRecord::try_from(take(value, &mut offset, file_record_size)?)?;
 
             files.push(record);
         }
 
-        let path_count = u32::from_le_bytes(value[offset..offset + 4].try_into().unwrap());
-        offset += 4;
-        let mut paths = Vec::with_capacity(path_count as usize);
+        let path_count = read_count(value, &mut offset)?;
+        let path_size = std::mem::size_of::<PathRecord>();
+        let mut paths = Vec::with_capacity(path_count.min(value.len() / path_size));
 
         for _ in 0..path_count {
-            let path_size = std::mem::size_of::<PathRecord>();
-            let record = PathRecord::try_from(&value[offset..offset + path_size])?;
-            offset += path_size;
+            let record = PathRecord::try_from(take(value, &mut offset, path_size)?)?;
 
             paths.push(record);
         }
 
-        let data = value[offset..].to_vec();
-        let _path_bundle = Bundle::try_from(data.as_slice()).unwrap();
+        let path_bundle = Bundle::try_from(&value[offset..])?;
 
         Ok(Self {
             bundles: bundles.into(),
             files: files.into(),
             paths: paths.into(),
-            path_bundle: _path_bundle,
+            path_bundle,
             cache: OnceLock::new(),
+            by_hash: OnceLock::new(),
         })
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct BundleRecord {
-    path: Arc<str>,
-    uncompressed_size: u32,
+/// Whether the virtual `path` starts with `prefix`, ignoring case and a
+/// leading `/` on the prefix.
+fn is_under(path: &Path, prefix: &str) -> bool {
+    let prefix = prefix.trim_start_matches('/');
+    path.to_str()
+        .and_then(|path| path.get(..prefix.len()))
+        .is_some_and(|head| head.eq_ignore_ascii_case(prefix))
 }
 
-impl BundleRecord {
-    pub fn size(&self) -> usize {
-        4 + self.path.len() + 4
-    }
-    pub fn path(&self) -> &str {
-        &self.path
+/// Maps every path to just its file name for [`ExtractOptions::flat`]. Paths
+/// are named in sorted order, the first to claim a name keeps it and the rest
+/// get a counter appended to the stem, e.g. `foo.dds`, `foo_1.dds`.
+pub fn flatten_paths(mut paths: Vec<PathBuf>) -> HashMap<PathBuf, PathBuf> {
+    paths.sort();
+    paths.dedup();
+
+    let mut used = HashSet::with_capacity(paths.len());
+    let mut names = HashMap::with_capacity(paths.len());
+    for path in paths {
+        let mut name = PathBuf::from(path.file_name().unwrap_or(path.as_os_str()));
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = path.extension().map(|ext| ext.to_string_lossy());
+
+        let mut counter = 1;
+        while !used.insert(name.clone()) {
+            name = match &ext {
+                Some(ext) => format!("{stem}_{counter}.{ext}"),
+                None => format!("{stem}_{counter}"),
+            }
+            .into();
+            counter += 1;
+        }
+        names.insert(path, name);
     }
-}
 
-impl From<BundleRecord> for Vec<u8> {
-    fn from(val: BundleRecord) -> Self {
-        let mut data = Vec::with_capacity(val.size());
-        let path_len = val.path.len() as u32;
+    names
+}
 
-        data.extend_from_slice(&path_len.to_le_bytes());
-        data.extend(val.path.as_bytes());
-        data.extend_from_slice(&val.uncompressed_size.to_le_bytes());
+/// A file and the path of the file written with the same bytes
+type Duplicate<'a> = (&'a (PathBuf, FileRecord), &'a Path);
+
+/// Keeps the first file for every distinct range of a bundle and pairs the rest
+/// with the path of the file they duplicate. Empty files are always kept.
+fn split_duplicates(
+    files: Vec<&(PathBuf, FileRecord)>,
+) -> (Vec<&(PathBuf, FileRecord)>, Vec<Duplicate<'_>>) {
+    let mut sources: HashMap<(u32, u32), &Path> = HashMap::new();
+    let mut unique = Vec::with_capacity(files.len());
+    let mut duplicates = vec![];
+
+    for file in files {
+        let (path, info) = file;
+        if info.size == 0 {
+            unique.push(file);
+            continue;
+        }
 
-        data
+        match sources.entry((info.offset, info.size)) {
+            Entry::Occupied(entry) => duplicates.push((file, *entry.get())),
+            Entry::Vacant(entry) => {
+                entry.insert(path);
+                unique.push(file);
+            }
+        }
     }
-}
-impl From<&BundleRecord> for Vec<u8> {
-    fn from(val: &BundleRecord) -> Self {
-        let mut data = Vec::with_capacity(val.size());
-        let path_len = val.path.len() as u32;
 
-        data.extend_from_slice(&path_len.to_le_bytes());
-        data.extend(val.path.as_bytes());
-        data.extend_from_slice(&val.uncompressed_size.to_le_bytes());
+    (unique, duplicates)
+}
 
-        data
-    }
+/// The first of `roots` that has the bundle, whole or split into parts.
+fn find_bundle(roots: &[PathBuf], bundlebin_path: &str) -> Option<PathBuf> {
+    roots
+        .iter()
+        .map(|root| root.join(bundlebin_path))
+        .find(|bundle_path| bundle_path.exists() || part_path(bundle_path, 0).exists())
 }
-impl From<BundleRecord> for Arc<[u8]> {
-    fn from(val: BundleRecord) -> Self {
-        let mut data = Vec::with_capacity(val.size());
-        let path_len = val.path.len() as u32;
 
-        data.extend_from_slice(&path_len.to_le_bytes());
-        data.extend(val.path.as_bytes());
-        data.extend_from_slice(&val.uncompressed_size.to_le_bytes());
+/// Runs `f` again up to `retries` times while it fails with an error that's
+/// likely to go away on its own, like a file briefly locked by antivirus or a
+/// sync client. The delay between attempts doubles from 50ms.
+fn retry<T>(retries: u32, mut f: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut delay = Duration::from_millis(50);
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                #[cfg(feature = "tracing")]
+                warn!("Retrying after {delay:?}: {e}");
 
-        data.into()
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
     }
 }
 
-impl TryFrom<&[u8]> for BundleRecord {
-    type Error = io::Error;
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::PermissionDenied | io::ErrorKind::Interrupted | io::ErrorKind::TimedOut
+    )
+}
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        if value.len() < 4 {
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "Insufficient data",
-            ));
+/// Links `dst` to the already written `src`, falling back to a copy when the
+/// link can't be made, e.g. across filesystems or without symlink permissions.
+fn link(dedup: Dedup, src: &Path, dst: &Path) -> io::Result<()> {
+    if let Some(parent) = dst.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::remove_file(dst) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    let linked = match dedup {
+        Dedup::Off => return std::fs::copy(src, dst).map(|_| ()),
+        Dedup::Hardlink => std::fs::hard_link(src, dst),
+        Dedup::Symlink => {
+            let src = std::path::absolute(src)?;
+            #[cfg(unix)]
+            let linked = std::os::unix::fs::symlink(src, dst);
+            #[cfg(windows)]
+            let linked = std::os::windows::fs::symlink_file(src, dst);
+
+            linked
         }
+    };
 
-        let str_len =
-            u32::from_le_bytes(value[0..4].try_into().map_err(io::Error::other)?) as usize;
+    if let Err(_e) = linked {
+        #[cfg(feature = "tracing")]
+        warn!("Couldn't link {}, copying instead: {_e}", dst.display());
 
-        let record_size = 4 + str_len + 4;
+        std::fs::copy(src, dst)?;
+    }
 
-        if value.len() < record_size {
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "Insufficient data length",
-            ));
+    Ok(())
+}
+
+/// Writes `files` from `bundle` to the paths `dest` maps them to, one seek
+/// chunk at a time. Every file that
+/// overlaps a chunk gets its part of it appended, so only the current chunk is
+/// held in memory no matter how large the bundle or its files are. Chunks that
+/// none of the files touch are never decompressed.
+///
+/// A file that can't be written is passed to `on_error` and dropped. A chunk
+/// that can't be decompressed ends the bundle, only the files finished before
+/// it are returned. Every file that wasn't is passed to `on_error` with the
+/// chunk's error, and the ones already started are removed so that a resumed
+/// extraction doesn't take them for done.
+fn write_streamed<'a, T>(
+    bundle: &Bundle<T>,
+    files: impl IntoIterator<Item = &'a (PathBuf, FileRecord)>,
+    dest: &(impl Fn(&Path) -> PathBuf + Sync),
+    hash: ContentHash,
+    retries: u32,
+    on_file: &(impl Fn(&Path, u64, Option<Digest>) + Sync),
+    on_error: &(impl Fn(Option<&Path>, io::Error) + Sync),
+) -> Vec<Written> {
+    let mut files: Vec<_> = files.into_iter().collect();
+    files.sort_by_key(|(_, info)| info.offset);
+
+    let create = |path: &Path| -> io::Result<(PathBuf, std::fs::File)> {
+        let file_path = dest(path);
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = retry(retries, || std::fs::File::create(&file_path))?;
+        Ok((file_path, file))
+    };
+
+    let chunk_len = bundle.chunk_len();
+    let mut pending = files.into_iter().peekable();
+    let mut open: Vec<(&Path, FileRecord, PathBuf, std::fs::File, Option<Hasher>)> = vec![];
+    let mut written = vec![];
+
+    for index in 0..bundle.chunk_count() {
+        let chunk_start = index * chunk_len;
+        let chunk_end = chunk_start + bundle.chunk_raw_len(index) as usize;
+
+        while let Some((path, info)) =
+            pending.next_if(|(_, info)| (info.offset as usize) < chunk_end)
+        {
+            match create(path) {
+                Ok((file_path, file)) => {
+                    open.push((path, *info, file_path, file, Hasher::new(hash)))
+                }
+                Err(e) => on_error(Some(path), e),
+            }
+        }
+        if open.is_empty() {
+            continue;
         }
 
-        let path = std::str::from_utf8(&value[4..str_len + 4])
-            .expect("Invalid UTF-8")
-            .to_string()
-            .into();
+        let chunk = match bundle.decompress_chunk(index) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                let started = open.into_iter().map(|(path, _, file_path, file, _)| {
+                    drop(file);
+                    let _ = std::fs::remove_file(file_path);
+                    path
+                });
+                let left = pending.map(|(path, _)| path.as_path());
+                for path in started.chain(left) {
+                    on_error(Some(path), io::Error::new(e.kind(), e.to_string()));
+                }
+                return written;
+            }
+        };
+        let mut i = 0;
+        while i < open.len() {
+            let (path, info, _, file, hasher) = &mut open[i];
+            let start = info.offset as usize;
+            let end = start + info.size as usize;
+
+            let from = start.max(chunk_start) - chunk_start;
+            let to = end.min(chunk_end) - chunk_start;
+            if from < to {
+                // A failed attempt can leave part of it written, every attempt
+                // starts over where the previous chunk's part ended
+                let position = (chunk_start + from - start) as u64;
+                let appended = retry(retries, || {
+                    file.seek(SeekFrom::Start(position))?;
+                    file.write_all(&chunk[from..to])
+                });
+                if let Err(e) = appended {
+                    on_error(Some(path), e);
+                    let (_, _, file_path, file, _) = open.swap_remove(i);
+                    drop(file);
+                    let _ = std::fs::remove_file(file_path);
+                    continue;
+                }
+                if let Some(hasher) = hasher {
+                    hasher.update(&chunk[from..to]);
+                }
+            }
 
-        let uncompressed_size =
-            u32::from_le_bytes(value[str_len + 4..record_size].try_into().unwrap());
+            if end <= chunk_end {
+                let digest = hasher.as_ref().map(Hasher::finish);
+                on_file(path, info.size as u64, digest);
+                let (_, info, file_path, _, _) = open.swap_remove(i);
+                written.push((file_path, info.size as usize, digest));
+            } else {
+                i += 1;
+            }
+        }
+    }
 
-        Ok(BundleRecord {
-            path,
-            uncompressed_size,
-        })
+    // Empty files at the very end of the bundle don't overlap any chunk
+    for (path, info) in pending {
+        let file_path = match create(path) {
+            Ok((file_path, _)) => file_path,
+            Err(e) => {
+                on_error(Some(path), e);
+                continue;
+            }
+        };
+        let digest = Hasher::new(hash).map(|hasher| hasher.finish());
+        on_file(path, info.size as u64, digest);
+        written.push((file_path, info.size as usize, digest));
     }
+
+    written
 }
 
-#[derive(Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct FileRecord {
-    /// File Name in a murmurhash64
-    hash: u64,
-    bundle_idx: u32,
-    offset: u32,
-    size: u32,
+/// Takes the next `n` bytes of the index, failing instead of panicking when
+/// it's been cut short.
+fn take<'a>(value: &'a [u8], offset: &mut usize, n: usize) -> io::Result<&'a [u8]> {
+    let slice = value
+        .get(*offset..)
+        .and_then(|rest| rest.get(..n))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated index"))?;
+    *offset += n;
+
+    Ok(slice)
 }
 
-unsafe impl Zeroable for FileRecord {}
-unsafe impl Pod for FileRecord {}
+fn read_count(value: &[u8], offset: &mut usize) -> io::Result<usize> {
+    let bytes = take(value, offset, 4)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
 
-impl AsRef<[u8]> for FileRecord {
-    fn as_ref(&self) -> &[u8] {
-        bytemuck::bytes_of(self)
-    }
+/// A bundle compressed by [`Index::replace_file`] or [`IndexBuilder::build`], to
+/// be written to `Bundles2/{path}.bundle.bin`.
+#[derive(Debug, Clone)]
+pub struct ModifiedBundle {
+    path: Arc<str>,
+    bundle: Bundle<Vec<u8>>,
 }
 
-impl From<FileRecord> for Vec<u8> {
-    fn from(val: FileRecord) -> Self {
-        bytemuck::bytes_of(&val).to_vec()
+impl ModifiedBundle {
+    pub fn path(&self) -> &str {
+        &self.path
     }
-}
-impl From<FileRecord> for Arc<[u8]> {
-    fn from(val: FileRecord) -> Self {
-        bytemuck::bytes_of(&val).to_vec().into()
+    pub fn bundle(&self) -> &Bundle<Vec<u8>> {
+        &self.bundle
     }
 }
 
-impl FileRecord {
-    const FILE_RECORD_SIZE: usize = std::mem::size_of::<FileRecord>();
-}
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
 
-impl TryFrom<&[u8]> for FileRecord {
-    type Error = std::io::Error;
+    use proptest::{collection::btree_set, prelude::*};
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        if value.len() != Self::FILE_RECORD_SIZE {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "Insufficient data",
-            ));
-        }
+    use super::{
+        decode_paths, encode_paths, flatten_paths, is_under, read_parts, retry, split_duplicates,
+        write_extracted, write_json_str, write_streamed, ContentHash, Digest, ExtractOptions,
+        Extracted, FileRecord, Index, IndexBuilder,
+    };
+    use crate::Bundle;
 
-        let hash = u64::from_le_bytes(value[0..8].try_into().unwrap());
-        let bundle_index = u32::from_le_bytes(value[8..12].try_into().unwrap());
-        let offset = u32::from_le_bytes(value[12..16].try_into().unwrap());
-        let size = u32::from_le_bytes(value[16..20].try_into().unwrap());
+    #[test]
+    fn decompress() {
+        let slice = include_bytes!("../resources/_.index.bin");
+        let bundle: Bundle<Index> = Bundle::try_from(slice.as_slice()).unwrap();
 
-        Ok(Self {
-            hash,
-            bundle_idx: bundle_index,
-            offset,
-            size,
-        })
+        let _ = bundle.decompress().unwrap();
     }
-}
 
-#[derive(Debug, Clone, Copy)]
-#[repr(C, packed)]
-struct PathRecord {
-    hash: u64,
-    offset: u32,
-    size: u32,
-    recursive_length: u32,
-}
+    #[test]
+    fn compress() {
+        let slice = include_bytes!("../resources/_.index.bin");
+        let bundle: Bundle<Index> = Bundle::try_from(slice.as_slice()).unwrap();
 
-unsafe impl Zeroable for PathRecord {}
-unsafe impl Pod for PathRecord {}
+        let bundle = Bundle::new(bundle.decompress().unwrap()).unwrap().to_vec();
+        assert_eq!(bundle.len(), slice.len());
+        assert_eq!(bundle, slice);
+    }
 
-impl AsRef<[u8]> for PathRecord {
-    fn as_ref(&self) -> &[u8] {
-        bytemuck::bytes_of(self)
+    #[test]
+    fn encode_paths_round_trip() {
+        let paths = [
+            "root.txt",
+            "data/mods.datc64",
+            "data/stats.datc64",
+            "art/textures/foo.dds",
+            "art/textures/ui/bar.dds",
+            "art/models/baz.fmt",
+        ];
+        let files: Vec<_> = paths
+            .iter()
+            .map(|&path| {
+                let record = FileRecord {
+                    hash: 0,
+                    bundle_idx: 0,
+                    offset: 0,
+                    size: 0,
+                };
+                (path, record)
+            })
+            .collect();
+
+        let (records, bytes) = encode_paths(&files);
+        assert_eq!(records.len(), 3);
+
+        let decoded: BTreeSet<String> = records
+            .iter()
+            .flat_map(|record| decode_paths(&bytes, record).unwrap())
+            .collect();
+        assert_eq!(decoded, paths.map(String::from).into());
     }
-}
 
-impl From<PathRecord> for Vec<u8> {
-    fn from(val: PathRecord) -> Self {
-        bytemuck::bytes_of(&val).to_vec()
+    proptest! {
+        #[test]
+        fn paths_round_trip(
+            paths in btree_set("[a-z0-9_]{1,6}(/[a-z0-9_]{1,6}){0,4}(\\.[a-z0-9]{1,4})?", 1..64),
+        ) {
+            let record = FileRecord {
+                hash: 0,
+                bundle_idx: 0,
+                offset: 0,
+                size: 0,
+            };
+            let files: Vec<_> = paths.iter().map(|path| (path.as_str(), record)).collect();
+
+            let (records, bytes) = encode_paths(&files);
+            prop_assert_eq!(
+                records.iter().map(|record| record.size as usize).sum::<usize>(),
+                bytes.len()
+            );
+
+            let mut decoded = BTreeSet::new();
+            for record in &records {
+                for path in decode_paths(&bytes, record).unwrap() {
+                    // Every path is written exactly once
+                    prop_assert!(decoded.insert(path));
+                }
+            }
+            prop_assert_eq!(decoded, paths);
+        }
     }
-}
-impl From<PathRecord> for Arc<[u8]> {
-    fn from(val: PathRecord) -> Self {
-        bytemuck::bytes_of(&val).to_vec().into()
+
+    #[test]
+    fn serialize_path_bundle() {
+        let slice = include_bytes!("../resources/_.index.bin");
+        let bundle: Bundle<Vec<u8>> = Bundle::try_from(slice.as_slice()).unwrap();
+        let data = bundle.decompress().unwrap();
+
+        let index = Index::try_from(data.as_slice()).unwrap();
+        let path_bundle = index.path_bundle().to_vec();
+        assert!(data.ends_with(&path_bundle));
+        assert_eq!(path_bundle.len(), index.path_bundle().size());
     }
-}
 
-impl TryFrom<&[u8]> for PathRecord {
-    type Error = std::io::Error;
+    #[test]
+    fn truncated() {
+        let slice = include_bytes!("../resources/_.index.bin");
+        let bundle: Bundle<Vec<u8>> = Bundle::try_from(slice.as_slice()).unwrap();
+        let data = bundle.decompress().unwrap();
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        const PATH_SIZE: usize = std::mem::size_of::<PathRecord>();
-        if value.len() < PATH_SIZE {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "Insufficient data",
-            ));
+        for len in [0, 2, 100] {
+            let err = Index::try_from(&data[..len]).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
         }
+    }
 
-        let hash = u64::from_le_bytes(value[0..8].try_into().unwrap());
-        let offset = u32::from_le_bytes(value[8..12].try_into().unwrap());
-        let size = u32::from_le_bytes(value[12..16].try_into().unwrap());
-        let recursive_length = u32::from_le_bytes(value[16..20].try_into().unwrap());
+    #[test]
+    fn invalid_bundle_path() {
+        let mut data = vec![];
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&[0xc3, 0x28]);
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        let err = Index::try_from(data.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
 
-        Ok(Self {
-            hash,
+    #[test]
+    fn streamed() {
+        let data: Vec<u8> = (0..600_000u32).map(|i| (i % 251) as u8).collect();
+        let bundle: Bundle<Vec<u8>> = Bundle::new(data.clone()).unwrap();
+        assert!(bundle.chunk_count() > 1);
+
+        // Spans chunk boundaries, duplicates another file and is empty
+        let ranges = [
+            (0, 10),
+            (200_000, 300_000),
+            (200_000, 300_000),
+            (599_990, 10),
+            (600_000, 0),
+        ];
+        let files: Vec<(PathBuf, FileRecord)> = ranges
+            .iter()
+            .enumerate()
+            .map(|(i, &(offset, size))| {
+                let record = FileRecord {
+                    hash: 0,
+                    bundle_idx: 0,
+                    offset,
+                    size,
+                };
+                (PathBuf::from(format!("dir/{i}.bin")), record)
+            })
+            .collect();
+
+        let out = std::env::temp_dir().join("bundle-streamed");
+        let written = write_streamed(
+            &bundle,
+            &files,
+            &|path: &Path| out.join(path),
+            ContentHash::Xxh3,
+            0,
+            &|_: &Path, _, _| {},
+            &|path: Option<&Path>, e| panic!("{path:?}: {e}"),
+        );
+        assert_eq!(written.len(), files.len());
+
+        for ((path, _), &(offset, size)) in files.iter().zip(&ranges) {
+            let bytes = std::fs::read(out.join(path)).unwrap();
+            assert_eq!(bytes, data[offset as usize..(offset + size) as usize]);
+
+            let (_, _, digest) = written.iter().find(|(p, ..)| *p == out.join(path)).unwrap();
+            assert_eq!(
+                *digest,
+                Some(Digest::Xxh3(xxhash_rust::xxh3::xxh3_64(&bytes)))
+            );
+        }
+
+        // A file that can't be created doesn't stop the others
+        let blocker = out.join("blocker");
+        std::fs::write(&blocker, b"").unwrap();
+        let failed = std::sync::Mutex::new(vec![]);
+        let written = write_streamed(
+            &bundle,
+            &files,
+            &|path: &Path| match path == files[0].0 {
+                true => blocker.join(path),
+                false => out.join(path),
+            },
+            ContentHash::None,
+            0,
+            &|_: &Path, _, _| {},
+            &|path: Option<&Path>, _| failed.lock().unwrap().push(path.map(Path::to_path_buf)),
+        );
+        assert_eq!(written.len(), files.len() - 1);
+        assert_eq!(failed.into_inner().unwrap(), [Some(files[0].0.clone())]);
+
+        // A chunk that can't be decompressed fails every file from it on,
+        // without leaving the started ones behind
+        let mut chunks = bundle.chunks().to_vec();
+        chunks[1] = Arc::from(vec![0xff; chunks[1].len()]);
+        let corrupt: Bundle<Vec<u8>> = Bundle::from_parts(
+            chunks,
+            bundle.uncompressed_size(),
+            bundle.chunk_len(),
+            bundle.compressor().unwrap(),
+            bundle.chunks_independent(),
+            None,
+        )
+        .unwrap();
+        let broken = std::env::temp_dir().join("bundle-streamed-broken");
+        let failed = std::sync::Mutex::new(vec![]);
+        let written = write_streamed(
+            &corrupt,
+            &files,
+            &|path: &Path| broken.join(path),
+            ContentHash::None,
+            0,
+            &|_: &Path, _, _| {},
+            &|path: Option<&Path>, _| failed.lock().unwrap().push(path.unwrap().to_path_buf()),
+        );
+        assert_eq!(written.len(), 1);
+        let mut failed = failed.into_inner().unwrap();
+        failed.sort();
+        let mut expected: Vec<_> = files[1..].iter().map(|(path, _)| path.clone()).collect();
+        expected.sort();
+        assert_eq!(failed, expected);
+        assert!(!broken.join(&files[1].0).exists());
+
+        std::fs::remove_dir_all(out).unwrap();
+        std::fs::remove_dir_all(broken).unwrap();
+    }
+
+    #[test]
+    fn duplicates() {
+        let record = |offset, size| FileRecord {
+            hash: 0,
+            bundle_idx: 0,
             offset,
             size,
-            recursive_length,
-        })
+        };
+        let files = [
+            (PathBuf::from("a"), record(0, 4)),
+            (PathBuf::from("b"), record(4, 4)),
+            (PathBuf::from("c"), record(0, 4)),
+            (PathBuf::from("d"), record(8, 0)),
+            (PathBuf::from("e"), record(8, 0)),
+        ];
+
+        let (unique, duplicates) = split_duplicates(files.iter().collect());
+        let unique: Vec<_> = unique.iter().map(|(path, _)| path.as_path()).collect();
+        assert_eq!(unique, ["a", "b", "d", "e"].map(Path::new));
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].0 .0, Path::new("c"));
+        assert_eq!(duplicates[0].1, Path::new("a"));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::Index;
-    use crate::Bundle;
+    #[test]
+    fn under() {
+        let path = Path::new("art/2ditems/armours/helmet.dds");
+        assert!(is_under(path, "Art/2DItems/"));
+        assert!(is_under(path, "/art/2ditems"));
+        assert!(is_under(path, ""));
+        assert!(!is_under(path, "art/textures/"));
+        assert!(!is_under(Path::new("art"), "art/2ditems/"));
+    }
 
     #[test]
-    fn decompress() {
-        let slice = include_bytes!("../resources/_.index.bin");
-        let bundle: Bundle<Index> = Bundle::try_from(slice.as_slice()).unwrap();
+    fn flatten() {
+        let paths = [
+            "art/b/foo.dds",
+            "art/a/foo.dds",
+            "foo_1.dds",
+            "data/readme",
+            "other/readme",
+        ];
+        let names = flatten_paths(paths.iter().map(PathBuf::from).collect());
+        let name = |path: &str| names[Path::new(path)].to_str().unwrap();
+
+        assert_eq!(name("art/a/foo.dds"), "foo.dds");
+        assert_eq!(name("art/b/foo.dds"), "foo_1.dds");
+        // Taken by the counter of an earlier path
+        assert_eq!(name("foo_1.dds"), "foo_1_1.dds");
+        assert_eq!(name("data/readme"), "readme");
+        assert_eq!(name("other/readme"), "readme_1");
+    }
 
-        let _ = bundle.decompress().unwrap();
+    #[test]
+    fn open_missing() {
+        let dir = std::env::temp_dir().join("bundle-open-missing");
+        let error = Index::open(&dir).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        assert!(error.to_string().contains("_.index.bin"));
     }
 
     #[test]
-    fn compress() {
-        let slice = include_bytes!("../resources/_.index.bin");
-        let bundle: Bundle<Index> = Bundle::try_from(slice.as_slice()).unwrap();
+    fn extract_missing() {
+        let mut builder = IndexBuilder::new();
+        builder.add_file("a.txt", b"a".to_vec());
+        let (index, _) = builder.build().unwrap();
+
+        let dir = std::env::temp_dir().join("bundle-extract-missing");
+        let options = ExtractOptions::default();
+        let error = index
+            .extract(index.iter_bundles(), &dir, dir.join("out"), &options)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        assert!(error.to_string().contains("Bundles2"));
+    }
 
-        let bundle = Bundle::new(bundle.decompress().unwrap()).unwrap().to_vec();
-        assert_eq!(bundle.len(), slice.len());
-        assert_eq!(bundle, slice);
+    #[test]
+    fn corrupt_path_bundle() {
+        let mut builder = IndexBuilder::new();
+        builder.add_file("a.txt", b"a".to_vec());
+        let (mut index, _) = builder.build().unwrap();
+
+        let path_bundle = &index.path_bundle;
+        let garbage = path_bundle
+            .chunks()
+            .iter()
+            .map(|chunk| Arc::from(vec![0xff; chunk.len()]))
+            .collect();
+        index.path_bundle = Bundle::from_parts(
+            garbage,
+            path_bundle.uncompressed_size(),
+            path_bundle.chunk_len(),
+            path_bundle.compressor().unwrap(),
+            path_bundle.chunks_independent(),
+            None,
+        )
+        .unwrap();
+
+        assert!(index.check_paths().is_err());
+        assert_eq!(index.iter_files().count(), 0);
+        assert_eq!(index.unresolved_files().len(), 1);
+        assert!(index.write_hashes(vec![]).is_err());
+    }
+
+    #[test]
+    fn parts() {
+        let dir = std::env::temp_dir().join("bundle-parts");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("split.bundle.bin");
+        std::fs::write(dir.join("split.bundle.bin.part0"), b"abc").unwrap();
+        std::fs::write(dir.join("split.bundle.bin.part1"), b"def").unwrap();
+
+        assert_eq!(read_parts(&path).unwrap().unwrap(), b"abcdef");
+        assert!(read_parts(&dir.join("missing.bundle.bin"))
+            .unwrap()
+            .is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn retries() {
+        let mut attempts = 0;
+        let result = retry(2, || {
+            attempts += 1;
+            match attempts {
+                1 | 2 => Err(std::io::ErrorKind::Interrupted.into()),
+                _ => Ok(attempts),
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut attempts = 0;
+        let result: std::io::Result<()> = retry(2, || {
+            attempts += 1;
+            Err(std::io::ErrorKind::NotFound.into())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn json_str() {
+        let mut out = vec![];
+        write_json_str(&mut out, "art/\"quoted\"\\path\n").unwrap();
+        assert_eq!(out, br#""art/\"quoted\"\\path\u000a""#);
+    }
+
+    #[test]
+    fn extracted() {
+        let entries = [
+            Extracted {
+                path: Path::new("data/mods.datc64"),
+                bundle: "Data",
+                size: 4,
+                digest: None,
+            },
+            Extracted {
+                path: Path::new("root.txt"),
+                bundle: "Root",
+                size: 0,
+                digest: Some(Digest::Xxh3(0xab)),
+            },
+        ];
+
+        let mut out = vec![];
+        write_extracted(entries, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[\n  {\"path\":\"data/mods.datc64\",\"bundle\":\"Data\",\"size\":4},\n  {\"path\":\"root.txt\",\"bundle\":\"Root\",\"size\":0,\"hash\":\"00000000000000ab\"}\n]\n"
+        );
     }
 }
@@ -0,0 +1,218 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, OnceLock},
+};
+
+use super::{encode_paths, hash_path, BundleRecord, FileRecord, Index, ModifiedBundle};
+use crate::{Bundle, BundleError};
+
+/// Default upper bound for the uncompressed size of a bundle written by [`IndexBuilder`].
+const DEFAULT_BUNDLE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Builds a new [`Index`] and its bundles from scratch.
+///
+/// Files are packed into bundles in the order they are added, starting a new
+/// bundle whenever the current one would grow past [`IndexBuilder::bundle_size`].
+/// Paths are stored lowercase with `/` separators, like the game's.
+#[derive(Debug, Clone)]
+pub struct IndexBuilder {
+    files: Vec<(String, Vec<u8>)>,
+    bundle_size: usize,
+}
+
+impl IndexBuilder {
+    pub fn new() -> Self {
+        Self {
+            files: vec![],
+            bundle_size: DEFAULT_BUNDLE_SIZE,
+        }
+    }
+
+    /// Sets the uncompressed size a bundle may grow to before a new one is started.
+    pub fn bundle_size(mut self, bundle_size: usize) -> Self {
+        self.bundle_size = bundle_size;
+        self
+    }
+
+    pub fn add_file(&mut self, path: &str, bytes: impl Into<Vec<u8>>) -> &mut Self {
+        let path = path.replace('\\', "/").to_lowercase();
+        self.files.push((path, bytes.into()));
+        self
+    }
+
+    /// Compresses every bundle and builds the index that points into them. The
+    /// bundles need to be written to `Bundles2/{path}.bundle.bin` next to it.
+    ///
+    /// Fails if a path was added twice, both would get the same hash and only
+    /// one of them could be looked up.
+    pub fn build(self) -> Result<(Index, Vec<ModifiedBundle>), BundleError> {
+        let mut seen = HashSet::with_capacity(self.files.len());
+        if let Some((path, _)) = self.files.iter().find(|(path, _)| !seen.insert(path)) {
+            return Err(BundleError::DuplicatePath(path.clone()));
+        }
+
+        let mut bundles: Vec<(BundleRecord, Vec<u8>)> = vec![];
+        let mut files = Vec::with_capacity(self.files.len());
+
+        for (path, bytes) in &self.files {
+            let full = bundles.last().is_none_or(|(_, data)| {
+                !data.is_empty() && data.len() + bytes.len() > self.bundle_size
+            });
+            if full {
+                let record = BundleRecord {
+                    path: format!("bundle_{}", bundles.len()).into(),
+                    uncompressed_size: 0,
+                };
+                bundles.push((record, vec![]));
+            }
+
+            let bundle_idx = bundles.len() - 1;
+            let (record, data) = &mut bundles[bundle_idx];
+            let offset = data.len() as u32;
+            data.extend_from_slice(bytes);
+            record.uncompressed_size = data.len() as u32;
+
+            files.push((
+                path.as_str(),
+                FileRecord {
+                    hash: hash_path(path),
+                    bundle_idx: bundle_idx as u32,
+                    offset,
+                    size: bytes.len() as u32,
+                },
+            ));
+        }
+
+        let (paths, path_data) = encode_paths(&files);
+        let path_bundle = Bundle::new(Arc::<[u8]>::from(path_data))?;
+
+        let mut records = Vec::with_capacity(bundles.len());
+        let mut compressed = Vec::with_capacity(bundles.len());
+        for (record, data) in bundles {
+            compressed.push(ModifiedBundle {
+                path: record.path.clone(),
+                bundle: Bundle::new(data)?,
+            });
+            records.push(record);
+        }
+
+        let index = Index {
+            bundles: records.into(),
+            files: files.into_iter().map(|(_, record)| record).collect(),
+            paths: paths.into(),
+            path_bundle,
+            cache: OnceLock::new(),
+            by_hash: OnceLock::new(),
+        };
+
+        Ok((index, compressed))
+    }
+}
+
+impl Default for IndexBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Index, IndexBuilder};
+    use crate::BundleError;
+
+    #[test]
+    fn build() {
+        let mut builder = IndexBuilder::new().bundle_size(12);
+        builder
+            .add_file("Data/Mods.datc64", b"mods".to_vec())
+            .add_file("data/stats.datc64", b"stats!!".to_vec())
+            .add_file("root.txt", b"root".to_vec());
+
+        let (index, bundles) = builder.build().unwrap();
+        assert_eq!(index.total_files(), 3);
+        assert_eq!(bundles.len(), 2);
+        assert_eq!(index.bundle_count(), 2);
+        assert_eq!(
+            index.bundle(1).map(|bundle| bundle.path()),
+            Some("bundle_1")
+        );
+        assert!(index.bundle(2).is_none());
+        assert_eq!(
+            index.file_by_path("root.txt").map(|file| file.bundle_idx()),
+            Some(1)
+        );
+        assert!(index.file_by_path("data/missing.datc64").is_none());
+        assert!(index.contains("Data/Stats.datc64"));
+        assert!(!index.contains("data/missing.datc64"));
+        assert_eq!(index.bundle_for_file("ROOT.txt"), Some("bundle_1"));
+        assert_eq!(index.bundle_for_file("data/missing.datc64"), None);
+
+        let mut paths: Vec<_> = index.iter_files().map(|(path, _)| path).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            ["data/mods.datc64", "data/stats.datc64", "root.txt"].map(Path::new)
+        );
+        assert!(index.unresolved_files().is_empty());
+        assert!(index.orphan_paths().is_empty());
+
+        let mut hashes = vec![];
+        index.write_hashes(&mut hashes).unwrap();
+        let hashes = String::from_utf8(hashes).unwrap();
+        assert_eq!(hashes.lines().count(), 3);
+        assert!(hashes.lines().all(|line| line.split_once('\t').is_some()));
+        assert!(hashes.contains("\troot.txt\n"));
+
+        let data = bundles[0].bundle().decompress().unwrap();
+        assert_eq!(data, b"modsstats!!");
+    }
+
+    #[test]
+    fn empty() {
+        let (index, bundles) = IndexBuilder::new().build().unwrap();
+        assert_eq!(index.total_files(), 0);
+        assert_eq!(index.bundle_count(), 0);
+        assert!(bundles.is_empty());
+        assert!(index.check_paths().is_ok());
+        assert_eq!(index.iter_files().count(), 0);
+
+        let bytes = Vec::from(&index);
+        let index = Index::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(index.total_files(), 0);
+    }
+
+    #[test]
+    fn duplicate() {
+        let mut builder = IndexBuilder::new();
+        builder
+            .add_file("Data/Mods.datc64", b"a".to_vec())
+            .add_file("data\\mods.datc64", b"b".to_vec());
+
+        assert_eq!(
+            builder.build().unwrap_err(),
+            BundleError::DuplicatePath("data/mods.datc64".to_string())
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let mut old = IndexBuilder::new();
+        old.add_file("a.txt", b"a".to_vec())
+            .add_file("b.txt", b"b".to_vec())
+            .add_file("c.txt", b"c".to_vec());
+        let mut new = IndexBuilder::new();
+        new.add_file("a.txt", b"a".to_vec())
+            .add_file("c.txt", b"cc".to_vec())
+            .add_file("d.txt", b"d".to_vec());
+
+        let (old, _) = old.build().unwrap();
+        let (new, _) = new.build().unwrap();
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, [Path::new("d.txt")]);
+        assert_eq!(diff.removed, [Path::new("b.txt")]);
+        assert_eq!(diff.changed, [Path::new("c.txt")]);
+        assert_eq!(old.diff(&old), Default::default());
+    }
+}
@@ -0,0 +1,293 @@
+//! The fixed-layout records of `_.index.bin`. Only `core` and `alloc` are used
+//! here, so the parsing doesn't depend on `std` or Oodle and can be reused in
+//! `no_std` targets like WASM.
+
+use alloc::{string::ToString, sync::Arc, vec::Vec};
+use core::{fmt, mem::size_of};
+
+use bytemuck::{Pod, Zeroable};
+
+/// Why a record couldn't be read from its bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordError {
+    /// Fewer bytes than the record needs
+    Truncated { expected: usize, actual: usize },
+    /// A bundle path that isn't UTF-8
+    InvalidUtf8,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated { expected, actual } => write!(
+                f,
+                "truncated record: expected {expected} bytes, found {actual}"
+            ),
+            Self::InvalidUtf8 => write!(f, "bundle path isn't valid UTF-8"),
+        }
+    }
+}
+
+impl core::error::Error for RecordError {}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BundleRecord {
+    pub(crate) path: Arc<str>,
+    pub(crate) uncompressed_size: u32,
+}
+
+impl BundleRecord {
+    pub fn size(&self) -> usize {
+        4 + self.path.len() + 4
+    }
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    pub fn uncompressed_size(&self) -> u32 {
+        self.uncompressed_size
+    }
+}
+
+impl From<BundleRecord> for Vec<u8> {
+    fn from(val: BundleRecord) -> Self {
+        let mut data = Vec::with_capacity(val.size());
+        let path_len = val.path.len() as u32;
+
+        data.extend_from_slice(&path_len.to_le_bytes());
+        data.extend(val.path.as_bytes());
+        data.extend_from_slice(&val.uncompressed_size.to_le_bytes());
+
+        data
+    }
+}
+impl From<&BundleRecord> for Vec<u8> {
+    fn from(val: &BundleRecord) -> Self {
+        let mut data = Vec::with_capacity(val.size());
+        let path_len = val.path.len() as u32;
+
+        data.extend_from_slice(&path_len.to_le_bytes());
+        data.extend(val.path.as_bytes());
+        data.extend_from_slice(&val.uncompressed_size.to_le_bytes());
+
+        data
+    }
+}
+impl From<BundleRecord> for Arc<[u8]> {
+    fn from(val: BundleRecord) -> Self {
+        let mut data = Vec::with_capacity(val.size());
+        let path_len = val.path.len() as u32;
+
+        data.extend_from_slice(&path_len.to_le_bytes());
+        data.extend(val.path.as_bytes());
+        data.extend_from_slice(&val.uncompressed_size.to_le_bytes());
+
+        data.into()
+    }
+}
+
+impl TryFrom<&[u8]> for BundleRecord {
+    type Error = RecordError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() < 4 {
+            return Err(RecordError::Truncated {
+                expected: 4,
+                actual: value.len(),
+            });
+        }
+
+        let str_len = u32::from_le_bytes(value[0..4].try_into().unwrap()) as usize;
+
+        let record_size = 4 + str_len + 4;
+
+        if value.len() < record_size {
+            return Err(RecordError::Truncated {
+                expected: record_size,
+                actual: value.len(),
+            });
+        }
+
+        let path = core::str::from_utf8(&value[4..str_len + 4])
+            .map_err(|_| RecordError::InvalidUtf8)?
+            .to_string()
+            .into();
+
+        let uncompressed_size =
+            u32::from_le_bytes(value[str_len + 4..record_size].try_into().unwrap());
+
+        Ok(BundleRecord {
+            path,
+            uncompressed_size,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C, packed)]
+pub struct FileRecord {
+    /// File Name in a murmurhash64
+    pub(crate) hash: u64,
+    pub(crate) bundle_idx: u32,
+    pub(crate) offset: u32,
+    pub(crate) size: u32,
+}
+
+unsafe impl Zeroable for FileRecord {}
+unsafe impl Pod for FileRecord {}
+
+impl AsRef<[u8]> for FileRecord {
+    fn as_ref(&self) -> &[u8] {
+        bytemuck::bytes_of(self)
+    }
+}
+
+impl From<FileRecord> for Vec<u8> {
+    fn from(val: FileRecord) -> Self {
+        bytemuck::bytes_of(&val).to_vec()
+    }
+}
+impl From<FileRecord> for Arc<[u8]> {
+    fn from(val: FileRecord) -> Self {
+        bytemuck::bytes_of(&val).to_vec().into()
+    }
+}
+
+impl FileRecord {
+    const FILE_RECORD_SIZE: usize = size_of::<FileRecord>();
+
+    /// Index of the bundle holding this file, see [`Index::bundle`](super::Index::bundle).
+    pub fn bundle_idx(&self) -> usize {
+        self.bundle_idx as usize
+    }
+
+    /// Uncompressed size of the file in bytes.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+impl TryFrom<&[u8]> for FileRecord {
+    type Error = RecordError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() != Self::FILE_RECORD_SIZE {
+            return Err(RecordError::Truncated {
+                expected: Self::FILE_RECORD_SIZE,
+                actual: value.len(),
+            });
+        }
+
+        let hash = u64::from_le_bytes(value[0..8].try_into().unwrap());
+        let bundle_index = u32::from_le_bytes(value[8..12].try_into().unwrap());
+        let offset = u32::from_le_bytes(value[12..16].try_into().unwrap());
+        let size = u32::from_le_bytes(value[16..20].try_into().unwrap());
+
+        Ok(Self {
+            hash,
+            bundle_idx: bundle_index,
+            offset,
+            size,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C, packed)]
+pub struct PathRecord {
+    pub(crate) hash: u64,
+    pub(crate) offset: u32,
+    pub(crate) size: u32,
+    pub(crate) recursive_length: u32,
+}
+
+unsafe impl Zeroable for PathRecord {}
+unsafe impl Pod for PathRecord {}
+
+impl AsRef<[u8]> for PathRecord {
+    fn as_ref(&self) -> &[u8] {
+        bytemuck::bytes_of(self)
+    }
+}
+
+impl From<PathRecord> for Vec<u8> {
+    fn from(val: PathRecord) -> Self {
+        bytemuck::bytes_of(&val).to_vec()
+    }
+}
+impl From<PathRecord> for Arc<[u8]> {
+    fn from(val: PathRecord) -> Self {
+        bytemuck::bytes_of(&val).to_vec().into()
+    }
+}
+
+impl TryFrom<&[u8]> for PathRecord {
+    type Error = RecordError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        const PATH_SIZE: usize = size_of::<PathRecord>();
+        if value.len() < PATH_SIZE {
+            return Err(RecordError::Truncated {
+                expected: PATH_SIZE,
+                actual: value.len(),
+            });
+        }
+
+        let hash = u64::from_le_bytes(value[0..8].try_into().unwrap());
+        let offset = u32::from_le_bytes(value[8..12].try_into().unwrap());
+        let size = u32::from_le_bytes(value[12..16].try_into().unwrap());
+        let recursive_length = u32::from_le_bytes(value[16..20].try_into().unwrap());
+
+        Ok(Self {
+            hash,
+            offset,
+            size,
+            recursive_length,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BundleRecord, FileRecord, PathRecord, RecordError};
+
+    #[test]
+    fn records() {
+        let bundle = BundleRecord {
+            path: "Data".into(),
+            uncompressed_size: 7,
+        };
+        let bytes: Vec<u8> = (&bundle).into();
+        let parsed = BundleRecord::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(parsed.path(), "Data");
+        assert_eq!(parsed.uncompressed_size(), 7);
+        assert_eq!(
+            BundleRecord::try_from(&bytes[..6]).unwrap_err(),
+            RecordError::Truncated {
+                expected: 12,
+                actual: 6
+            }
+        );
+
+        let file = FileRecord {
+            hash: 1,
+            bundle_idx: 2,
+            offset: 3,
+            size: 4,
+        };
+        let parsed = FileRecord::try_from(file.as_ref()).unwrap();
+        assert_eq!(parsed.bundle_idx(), 2);
+        assert!(FileRecord::try_from(&file.as_ref()[..19]).is_err());
+
+        let path = PathRecord {
+            hash: 1,
+            offset: 2,
+            size: 3,
+            recursive_length: 4,
+        };
+        let parsed = PathRecord::try_from(path.as_ref()).unwrap();
+        assert_eq!({ parsed.recursive_length }, 4);
+    }
+}
@@ -1,19 +1,36 @@
+extern crate alloc;
+
+mod borrowed;
+mod error;
+pub mod filter;
 pub mod index;
+pub mod prelude;
 mod util;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use borrowed::BundleRef;
+pub use error::BundleError;
+pub use oodle_safe::{Compressor, DecodeThreadPhase, BLOCK_LEN};
 
 use std::{ffi::c_void, io::Read, marker::PhantomData, sync::Arc};
 
-use oodle_safe::{CompressOptions, BLOCK_LEN};
+use oodle_safe::CompressOptions;
 use oodle_sys::{
-    OodleLZSeekTable_Flags_OodleLZSeekTable_Flags_None, OodleLZ_CompressOptions_GetDefault,
-    OodleLZ_CompressionLevel_OodleLZ_CompressionLevel_Normal,
-    OodleLZ_Compressor_OodleLZ_Compressor_Hydra, OodleLZ_CreateSeekTable,
-    OodleLZ_GetCompressedBufferSizeNeeded, OodleLZ_GetSeekTableMemorySizeNeeded, OodleLZ_SeekTable,
+    OodleLZSeekTable_Flags_OodleLZSeekTable_Flags_MakeRawCRCs,
+    OodleLZSeekTable_Flags_OodleLZSeekTable_Flags_None, OodleLZ_CheckCRC_OodleLZ_CheckCRC_No,
+    OodleLZ_CompressOptions_GetDefault, OodleLZ_CompressionLevel_OodleLZ_CompressionLevel_Normal,
+    OodleLZ_Compressor_OodleLZ_Compressor_Hydra, OodleLZ_Compressor_OodleLZ_Compressor_Kraken,
+    OodleLZ_Compressor_OodleLZ_Compressor_Leviathan, OodleLZ_Compressor_OodleLZ_Compressor_Mermaid,
+    OodleLZ_Compressor_OodleLZ_Compressor_None, OodleLZ_Compressor_OodleLZ_Compressor_Selkie,
+    OodleLZ_CreateSeekTable, OodleLZ_Decode_ThreadPhase_OodleLZ_Decode_ThreadPhaseAll,
+    OodleLZ_Decompress, OodleLZ_FillSeekTable, OodleLZ_FuzzSafe_OodleLZ_FuzzSafe_Yes,
+    OodleLZ_GetCompressedBufferSizeNeeded, OodleLZ_GetFirstChunkCompressor,
+    OodleLZ_GetSeekTableMemorySizeNeeded, OodleLZ_SeekTable,
+    OodleLZ_Verbosity_OodleLZ_Verbosity_None,
 };
 use rayon::prelude::*;
 
-use crate::util::{read_bytes, read_i32, read_i64, read_u32};
-
 #[derive(Debug, Clone)]
 pub struct Bundle<T = Arc<[u8]>> {
     uncompressed_size: u32,
@@ -31,8 +48,202 @@ impl<T> Bundle<T> {
         12 + self.seek_table_size as usize + self.compressed_size as usize
     }
 
+    /// Length of the decompressed data, as recorded in the header.
+    pub fn uncompressed_size(&self) -> u32 {
+        self.uncompressed_size
+    }
+
+    /// Whether the bundle decompresses to nothing. Empty bundles have no chunks
+    /// and decompress to an empty buffer without calling into Oodle.
+    pub fn is_empty(&self) -> bool {
+        self.uncompressed_size == 0
+    }
+
+    /// Compressed size over uncompressed size, lower is better. NaN for an
+    /// empty bundle.
+    pub fn compression_ratio(&self) -> f64 {
+        self.compressed_size as f64 / self.uncompressed_size as f64
+    }
+
+    /// The raw `OodleLZ_Compressor` id recorded in the seek table.
+    pub fn seek_table_compressor(&self) -> i32 {
+        self.seek_table.compressor
+    }
+
+    /// [`Bundle::seek_table_compressor`] as a [`Compressor`], `None` for ids
+    /// Oodle doesn't define.
+    pub fn compressor(&self) -> Option<Compressor> {
+        compressor_from_id(self.seek_table.compressor)
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.seek_table.numSeekChunks as usize
+    }
+
+    pub fn chunk_comp_lens(&self) -> &[u32] {
+        &self.seek_chunk_comp_lens
+    }
+
+    /// Uncompressed length of the chunk at `index`. Every chunk is
+    /// `seekChunkLen` long except for the last one, which holds the remainder.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn chunk_raw_len(&self, index: usize) -> u32 {
+        assert!(index < self.chunk_count(), "chunk index out of bounds");
+
+        let chunk_len = self.seek_table.seekChunkLen as i64;
+        let start = index as i64 * chunk_len;
+        (self.seek_table.totalRawLen - start).min(chunk_len) as u32
+    }
+
+    /// Checks that the header, seek table and chunks agree with each other.
+    pub fn verify(&self) -> Result<(), BundleError> {
+        verify_parts(
+            self.uncompressed_size,
+            self.compressed_size,
+            self.seek_table_size,
+            &self.seek_table,
+            &self.seek_chunk_comp_lens,
+            self.chunks.len(),
+            self.raw_crcs.as_ref().map(|crcs| crcs.len()),
+        )
+    }
+
+    /// Decompresses every chunk and computes its raw CRC with Oodle in parallel,
+    /// e.g. to fill in [`Bundle::set_raw_crcs`] for a bundle made with
+    /// [`Bundle::new`].
+    pub fn compute_crcs(&self) -> std::io::Result<Vec<u32>> {
+        let chunks = &self.chunks;
+        let chunk_len = self.seek_table.seekChunkLen;
+        let total_len = self.seek_table.totalRawLen;
+        let compressor = self.seek_table.compressor;
+
+        if !self.chunks_independent() {
+            let raw =
+                decompress_dependent(chunks, total_len as usize, chunk_len as usize, compressor)?;
+            return raw
+                .chunks(chunk_len as usize)
+                .zip(chunks.iter())
+                .map(|(raw, chunk)| chunk_crc(raw, chunk, chunk_len))
+                .collect();
+        }
+
+        (0..chunks.len())
+            .into_par_iter()
+            .map(|index| {
+                let start = index as i64 * chunk_len as i64;
+                let mut raw = vec![0; (total_len - start).min(chunk_len as i64) as usize];
+                decompress_chunk_into(
+                    &chunks[index],
+                    &mut raw,
+                    index,
+                    compressor,
+                    &DecodeThreadPhase::All,
+                )?;
+
+                chunk_crc(&raw, &chunks[index], chunk_len)
+            })
+            .collect()
+    }
+
+    /// Sets the per-chunk raw CRCs written after the chunks by
+    /// [`Bundle::to_vec`], growing the seek table size in the header to match.
+    pub fn set_raw_crcs(&mut self, crcs: Vec<u32>) -> Result<(), BundleError> {
+        if crcs.len() != self.chunk_count() {
+            return Err(BundleError::CrcCount {
+                expected: self.chunk_count(),
+                actual: crcs.len(),
+            });
+        }
+
+        if self.raw_crcs.is_none() {
+            self.seek_table_size += (crcs.len() * size_of::<u32>()) as u32;
+        }
+        let crcs: Arc<[u32]> = crcs.into();
+        self.seek_table.rawCRCs = crcs.as_ptr() as *mut _;
+        self.raw_crcs = Some(crcs);
+
+        Ok(())
+    }
+
+    /// Assembles a bundle from chunks that are already compressed, e.g. to only
+    /// recompress the chunks of a large bundle that changed. Every chunk but
+    /// the last decompresses to `chunk_len` bytes, `independent` says whether
+    /// they each reset the decoder, like [`Bundle::new`]'s do.
+    pub fn from_parts(
+        chunks: Vec<Arc<[u8]>>,
+        uncompressed_size: u32,
+        chunk_len: usize,
+        compressor: Compressor,
+        independent: bool,
+        raw_crcs: Option<Vec<u32>>,
+    ) -> Result<Self, BundleError> {
+        // The rest of the layout is checked by `verify`
+        let chunk_len =
+            i32::try_from(chunk_len).map_err(|_| BundleError::ChunkLen(chunk_len as i64))?;
+
+        let compressed: u64 = chunks.iter().map(|chunk| chunk.len() as u64).sum();
+        let compressed_size =
+            u32::try_from(compressed).map_err(|_| BundleError::TooLarge(compressed))?;
+        let seek_chunk_comp_lens: Arc<[u32]> =
+            chunks.iter().map(|chunk| chunk.len() as u32).collect();
+        let raw_crcs: Option<Arc<[u32]>> = raw_crcs.map(Into::into);
+
+        let tables = chunks.len() + raw_crcs.as_ref().map_or(0, |crcs| crcs.len());
+        let seek_table = OodleLZ_SeekTable {
+            compressor: compressor.into(),
+            seekChunksIndependent: independent.into(),
+            totalRawLen: uncompressed_size as i64,
+            totalCompLen: compressed as i64,
+            numSeekChunks: chunks.len() as i32,
+            seekChunkLen: chunk_len,
+            seekChunkCompLens: seek_chunk_comp_lens.as_ptr() as *mut _,
+            rawCRCs: raw_crcs
+                .as_ref()
+                .map_or(std::ptr::null_mut(), |crcs| crcs.as_ptr() as *mut _),
+        };
+
+        let bundle = Self {
+            uncompressed_size,
+            compressed_size,
+            seek_table_size: (size_of::<OodleLZ_SeekTable>() + tables * size_of::<u32>()) as u32,
+            seek_table,
+            seek_chunk_comp_lens,
+            raw_crcs,
+            chunks: chunks.into(),
+            _marker: PhantomData,
+        };
+        bundle.verify()?;
+
+        Ok(bundle)
+    }
+
+    /// The compressed seek chunks, in order.
+    pub fn chunks(&self) -> &[Arc<[u8]>] {
+        &self.chunks
+    }
+
+    /// Reinterprets the payload type without touching the compressed data, so a
+    /// bundle parsed once can be decompressed into different views.
+    pub fn map<U>(self) -> Bundle<U> {
+        Bundle {
+            uncompressed_size: self.uncompressed_size,
+            compressed_size: self.compressed_size,
+            seek_table_size: self.seek_table_size,
+            seek_table: self.seek_table,
+            seek_chunk_comp_lens: self.seek_chunk_comp_lens,
+            raw_crcs: self.raw_crcs,
+            chunks: self.chunks,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Serializes the bundle in the on-disk layout read by `TryFrom<&[u8]>`: the
+    /// 12 byte header, the seek table with its pointers zeroed, the per-chunk
+    /// compressed lengths, the chunks and the raw CRCs if there are any. The
+    /// `From<Bundle<T>>` impls for `Vec<u8>` and `Arc<[u8]>` all go through this.
     pub fn to_vec(&self) -> Vec<u8> {
-        let mut data: Vec<u8> = Vec::with_capacity(self.compressed_size as usize);
+        let mut data: Vec<u8> = Vec::with_capacity(self.size());
 
         data.extend_from_slice(&self.uncompressed_size.to_le_bytes());
         data.extend_from_slice(&self.compressed_size.to_le_bytes());
@@ -69,7 +280,7 @@ where
         let mut buf = vec![];
         reader.read_to_end(&mut buf)?;
 
-        Ok(Self::try_from(buf.as_slice()).unwrap())
+        Self::try_from(buf.as_slice())
     }
 
     pub fn from_slice(slice: &[u8]) -> std::io::Result<Self> {
@@ -77,93 +288,596 @@ where
     }
 
     pub fn decompress(&self) -> std::io::Result<T> {
-        T::try_from(self._decompress()?.as_slice())
+        self.decompress_with(DecodeThreadPhase::All)
+    }
+
+    /// Same as [`Bundle::decompress`], but runs Oodle's decoder in the given
+    /// `phase` instead of doing both phases on the same thread.
+    pub fn decompress_with(&self, phase: DecodeThreadPhase) -> std::io::Result<T> {
+        T::try_from(self._decompress(&phase)?.as_slice())
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", e)))
     }
 
-    fn _decompress(&self) -> std::io::Result<Vec<u8>> {
+    /// Same as [`Bundle::decompress`], but decompresses the chunks on `pool`
+    /// instead of rayon's global pool, for applications that keep their own.
+    pub fn decompress_in(&self, pool: &rayon::ThreadPool) -> std::io::Result<T> {
+        // Only the chunks and the plain seek table fields are sent to the pool,
+        // the seek table itself holds raw pointers
+        let chunks = &self.chunks;
         let total_size = self.seek_table.totalRawLen as usize;
         let block_size = self.seek_table.seekChunkLen as usize;
-        let mut buffer = vec![0; total_size];
+        let compressor = self.seek_table.compressor;
+        let independent = self.chunks_independent();
 
-        self.chunks
-            .par_iter()
-            .zip(buffer.par_chunks_mut(block_size))
-            .for_each(|(chunk, buf)| {
-                let res = oodle_safe::decompress(
-                    chunk,
-                    buf,
-                    None,
-                    None,
-                    None,
-                    Some(oodle_safe::DecodeThreadPhase::All),
-                );
+        let data = pool.install(|| {
+            decompress_chunks(
+                chunks,
+                total_size,
+                block_size,
+                compressor,
+                independent,
+                &DecodeThreadPhase::All,
+            )
+        })?;
 
-                let _bytes = res.unwrap();
-            });
+        T::try_from(data.as_slice())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", e)))
+    }
+
+    /// Same as [`Bundle::decompress`], but decompresses the chunks one after
+    /// another on the current thread. Use this when already running inside a
+    /// parallel iterator, like [`Index::extract`](crate::index::Index::extract)
+    /// does across bundles, so the chunks don't oversubscribe the thread pool.
+    pub fn decompress_sequential(&self) -> std::io::Result<T> {
+        let total_size = self.seek_table.totalRawLen as usize;
+        let block_size = self.seek_table.seekChunkLen as usize;
+        let buffer = if self.is_empty() {
+            vec![]
+        } else if self.chunks_independent() {
+            let mut buffer = vec![0; total_size];
+            self.chunks
+                .iter()
+                .enumerate()
+                .zip(buffer.chunks_mut(block_size))
+                .try_for_each(|((index, chunk), buf)| {
+                    let compressor = self.seek_table.compressor;
+                    decompress_chunk_into(chunk, buf, index, compressor, &DecodeThreadPhase::All)
+                })?;
+            buffer
+        } else {
+            decompress_dependent(
+                &self.chunks,
+                total_size,
+                block_size,
+                self.seek_table.compressor,
+            )?
+        };
+
+        T::try_from(buffer.as_slice())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", e)))
+    }
+
+    /// Same as [`Bundle::decompress`], but runs on tokio's blocking pool so it
+    /// doesn't stall the async runtime. Only the chunks are moved into the
+    /// blocking task, the seek table's raw pointers aren't `Send`.
+    #[cfg(feature = "tokio")]
+    pub fn decompress_async(
+        &self,
+    ) -> impl std::future::Future<Output = std::io::Result<T>> + Send + 'static
+    where
+        T: Send + 'static,
+    {
+        let chunks = self.chunks.clone();
+        let total_size = self.seek_table.totalRawLen as usize;
+        let block_size = self.seek_table.seekChunkLen as usize;
+        let compressor = self.seek_table.compressor;
+        let independent = self.chunks_independent();
+
+        async move {
+            tokio::task::spawn_blocking(move || {
+                let phase = DecodeThreadPhase::All;
+                let data = decompress_chunks(
+                    &chunks,
+                    total_size,
+                    block_size,
+                    compressor,
+                    independent,
+                    &phase,
+                )?;
+                T::try_from(data.as_slice()).map_err(|e| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", e))
+                })
+            })
+            .await
+            .map_err(std::io::Error::other)?
+        }
+    }
+}
+
+impl<T> Bundle<T> {
+    /// The decompressed bytes without parsing them into `T`. See
+    /// [`Bundle::decompress_view`] to parse a type that borrows from them.
+    pub fn decompress_borrowed(&self) -> std::io::Result<Vec<u8>> {
+        self._decompress(&DecodeThreadPhase::All)
+    }
+
+    /// Decompresses into `buffer` and parses `U` as a view of it, for types
+    /// that borrow their data instead of copying it out of the slice. The view
+    /// can't outlive `buffer`.
+    pub fn decompress_view<'a, U>(&self, buffer: &'a mut Vec<u8>) -> std::io::Result<U>
+    where
+        U: TryFrom<&'a [u8]>,
+        U::Error: std::fmt::Debug,
+    {
+        *buffer = self.decompress_borrowed()?;
+        U::try_from(buffer.as_slice())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", e)))
+    }
+
+    fn _decompress(&self, phase: &DecodeThreadPhase) -> std::io::Result<Vec<u8>> {
+        decompress_chunks(
+            &self.chunks,
+            self.seek_table.totalRawLen as usize,
+            self.seek_table.seekChunkLen as usize,
+            self.seek_table.compressor,
+            self.chunks_independent(),
+            phase,
+        )
+    }
+
+    /// Decompresses only the seek chunk at `index`, which covers the bytes
+    /// starting at `index * seekChunkLen`. Fails for every chunk but the first
+    /// when the chunks aren't independent, see [`Bundle::chunks_independent`].
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn decompress_chunk(&self, index: usize) -> std::io::Result<Vec<u8>> {
+        if index > 0 && !self.chunks_independent() {
+            return Err(BundleError::DependentChunk(index).into());
+        }
+
+        let mut buffer = vec![0; self.chunk_raw_len(index) as usize];
+        decompress_chunk_into(
+            &self.chunks[index],
+            &mut buffer,
+            index,
+            self.seek_table.compressor,
+            &DecodeThreadPhase::All,
+        )?;
 
         Ok(buffer)
     }
+
+    /// Decompresses `len` bytes starting at `offset` of the uncompressed data,
+    /// e.g. a [`FileRecord`](index::FileRecord)'s range, decoding only the seek
+    /// chunks that overlap it. Dependent chunks can't be decoded on their own,
+    /// so for those every chunk up to the end of the range is decoded.
+    pub fn decompress_range(&self, offset: usize, len: usize) -> std::io::Result<Vec<u8>> {
+        let total_size = self.seek_table.totalRawLen as usize;
+        let end = offset
+            .checked_add(len)
+            .filter(|&end| end <= total_size)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "range {offset}..{offset}+{len} is out of bounds of {total_size} bytes"
+                    ),
+                )
+            })?;
+        if len == 0 {
+            return Ok(vec![]);
+        }
+
+        let chunk_len = self.chunk_len();
+        let first = match self.chunks_independent() {
+            true => offset / chunk_len,
+            false => 0,
+        };
+        let last = (end - 1) / chunk_len;
+        let start = first * chunk_len;
+        let span = total_size.min((last + 1) * chunk_len) - start;
+        let chunks = &self.chunks[first..=last];
+        let compressor = self.seek_table.compressor;
+
+        let mut buffer = if self.chunks_independent() {
+            let mut buffer = vec![0; span];
+            chunks
+                .par_iter()
+                .zip(buffer.par_chunks_mut(chunk_len))
+                .enumerate()
+                .try_for_each(|(i, (chunk, buf))| {
+                    decompress_chunk_into(
+                        chunk,
+                        buf,
+                        first + i,
+                        compressor,
+                        &DecodeThreadPhase::All,
+                    )
+                })?;
+            buffer
+        } else {
+            decompress_dependent(chunks, span, chunk_len, compressor)?
+        };
+
+        buffer.truncate(end - start);
+        buffer.drain(..offset - start);
+        Ok(buffer)
+    }
+
+    /// Uncompressed length of every seek chunk but the last.
+    pub fn chunk_len(&self) -> usize {
+        self.seek_table.seekChunkLen as usize
+    }
+
+    /// Whether every seek chunk resets the decoder, so chunks can be decoded on
+    /// their own and in parallel. Bundles made by [`Bundle::new`] always do,
+    /// otherwise every chunk needs the ones before it as its window.
+    pub fn chunks_independent(&self) -> bool {
+        self.seek_table.seekChunksIndependent != 0
+    }
+}
+
+/// The [`Compressor`] for an `OodleLZ_Compressor` id, `None` for ids Oodle
+/// doesn't define.
+#[allow(non_upper_case_globals)]
+fn compressor_from_id(id: i32) -> Option<Compressor> {
+    match id {
+        OodleLZ_Compressor_OodleLZ_Compressor_None => Some(Compressor::None),
+        OodleLZ_Compressor_OodleLZ_Compressor_Kraken => Some(Compressor::Kraken),
+        OodleLZ_Compressor_OodleLZ_Compressor_Leviathan => Some(Compressor::Leviathan),
+        OodleLZ_Compressor_OodleLZ_Compressor_Mermaid => Some(Compressor::Mermaid),
+        OodleLZ_Compressor_OodleLZ_Compressor_Selkie => Some(Compressor::Selkie),
+        OodleLZ_Compressor_OodleLZ_Compressor_Hydra => Some(Compressor::Hydra),
+        _ => None,
+    }
+}
+
+/// Names an `OodleLZ_Compressor` id.
+#[allow(non_upper_case_globals)]
+pub fn compressor_name(id: i32) -> &'static str {
+    match id {
+        OodleLZ_Compressor_OodleLZ_Compressor_None => "None",
+        OodleLZ_Compressor_OodleLZ_Compressor_Kraken => "Kraken",
+        OodleLZ_Compressor_OodleLZ_Compressor_Mermaid => "Mermaid",
+        OodleLZ_Compressor_OodleLZ_Compressor_Selkie => "Selkie",
+        OodleLZ_Compressor_OodleLZ_Compressor_Hydra => "Hydra",
+        OodleLZ_Compressor_OodleLZ_Compressor_Leviathan => "Leviathan",
+        _ => "Invalid",
+    }
+}
+
+/// Lets Oodle fill a single-chunk seek table with `MakeRawCRCs` to get the CRC
+/// of `raw`, which `comp` decompresses to.
+fn chunk_crc(raw: &[u8], comp: &[u8], chunk_len: i32) -> std::io::Result<u32> {
+    let flags = OodleLZSeekTable_Flags_OodleLZSeekTable_Flags_MakeRawCRCs;
+    let size = unsafe { OodleLZ_GetSeekTableMemorySizeNeeded(1, flags) } as usize;
+    // The table is followed by its arrays, keep it 8 byte aligned for the i64s
+    let mut memory = vec![0u64; size.div_ceil(size_of::<u64>())];
+    let table = memory.as_mut_ptr() as *mut OodleLZ_SeekTable;
+
+    let filled = unsafe {
+        OodleLZ_FillSeekTable(
+            table,
+            flags,
+            chunk_len,
+            raw.as_ptr() as *const c_void,
+            raw.len() as isize,
+            comp.as_ptr() as *const c_void,
+            comp.len() as isize,
+        )
+    };
+
+    // SAFETY: Oodle pointed rawCRCs into `memory`, which is still alive
+    match unsafe { (*table).rawCRCs.as_ref() } {
+        Some(&crc) if filled != 0 => Ok(crc),
+        _ => Err(std::io::Error::other(
+            "Oodle failed to compute the chunk CRC",
+        )),
+    }
+}
+
+fn verify_parts(
+    uncompressed_size: u32,
+    compressed_size: u32,
+    seek_table_size: u32,
+    seek_table: &OodleLZ_SeekTable,
+    comp_lens: &[u32],
+    chunks: usize,
+    crcs: Option<usize>,
+) -> Result<(), BundleError> {
+    let chunk_count = seek_table.numSeekChunks as usize;
+    if chunk_count != chunks || chunk_count != comp_lens.len() {
+        return Err(BundleError::ChunkCount {
+            expected: chunk_count,
+            actual: chunks,
+        });
+    }
+
+    let comp_lens: u64 = comp_lens.iter().map(|&len| len as u64).sum();
+    if comp_lens != compressed_size as u64 {
+        return Err(BundleError::CompressedSize {
+            expected: compressed_size as u64,
+            actual: comp_lens,
+        });
+    }
+
+    if seek_table.totalRawLen != uncompressed_size as i64 {
+        return Err(BundleError::UncompressedSize {
+            expected: uncompressed_size as u64,
+            actual: seek_table.totalRawLen as u64,
+        });
+    }
+
+    // Everything that splits the data into chunks relies on these
+    let chunk_len = seek_table.seekChunkLen;
+    if chunk_len < BLOCK_LEN as i32 || !(chunk_len as u32).is_power_of_two() {
+        return Err(BundleError::ChunkLen(chunk_len as i64));
+    }
+    let expected = (uncompressed_size as usize).div_ceil(chunk_len as usize);
+    if chunk_count != expected {
+        return Err(BundleError::ChunkCount {
+            expected,
+            actual: chunk_count,
+        });
+    }
+
+    if let Some(crcs) = crcs {
+        if crcs != chunk_count {
+            return Err(BundleError::CrcCount {
+                expected: chunk_count,
+                actual: crcs,
+            });
+        }
+    }
+
+    // The seek table itself, then a `u32` per chunk for the compressed lengths
+    // and again for the raw CRCs, which come after the chunks
+    let tables = chunk_count + crcs.unwrap_or_default();
+    let actual = (size_of::<OodleLZ_SeekTable>() + tables * size_of::<u32>()) as u64;
+    if actual != seek_table_size as u64 {
+        return Err(BundleError::SeekTableSize {
+            expected: seek_table_size as u64,
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+fn decompress_chunks<C: AsRef<[u8]> + Sync>(
+    chunks: &[C],
+    total_size: usize,
+    block_size: usize,
+    compressor: i32,
+    independent: bool,
+    phase: &DecodeThreadPhase,
+) -> std::io::Result<Vec<u8>> {
+    // Nothing to zip the chunks with, and `par_chunks_mut` rejects a 0 block size
+    if total_size == 0 {
+        return Ok(vec![]);
+    }
+    if !independent {
+        return decompress_dependent(chunks, total_size, block_size, compressor);
+    }
+
+    let mut buffer = vec![0; total_size];
+
+    chunks
+        .par_iter()
+        .enumerate()
+        .zip(buffer.par_chunks_mut(block_size))
+        .try_for_each(|((index, chunk), buf)| {
+            decompress_chunk_into(chunk.as_ref(), buf, index, compressor, phase)
+        })?;
+
+    Ok(buffer)
+}
+
+/// Compression options shared by the threads of [`Bundle::new`].
+struct SharedOptions(Option<CompressOptions>);
+
+// SAFETY: the only pointer in the options is `jobifyUserPtr`, which is null in
+// the defaults and never dereferenced without a jobify callback
+unsafe impl Sync for SharedOptions {}
+
+/// Decompresses chunks that don't reset the decoder one after another, with
+/// everything decoded so far as the window the next chunk refers back to.
+fn decompress_dependent<C: AsRef<[u8]>>(
+    chunks: &[C],
+    total_size: usize,
+    block_size: usize,
+    compressor: i32,
+) -> std::io::Result<Vec<u8>> {
+    let mut buffer = vec![0u8; total_size];
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        let chunk = chunk.as_ref();
+        let start = index * block_size;
+        let len = block_size.min(total_size.saturating_sub(start));
+        if len == 0 {
+            break;
+        }
+
+        // SAFETY: the raw range lies within `buffer`, which is the window base
+        let base = buffer.as_mut_ptr();
+        let decoded = unsafe {
+            OodleLZ_Decompress(
+                chunk.as_ptr() as *const c_void,
+                chunk.len() as isize,
+                base.add(start) as *mut c_void,
+                len as isize,
+                OodleLZ_FuzzSafe_OodleLZ_FuzzSafe_Yes,
+                OodleLZ_CheckCRC_OodleLZ_CheckCRC_No,
+                OodleLZ_Verbosity_OodleLZ_Verbosity_None,
+                base as *mut c_void,
+                total_size as isize,
+                None,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                0,
+                OodleLZ_Decode_ThreadPhase_OodleLZ_Decode_ThreadPhaseAll,
+            )
+        };
+
+        if decoded as usize != len {
+            return Err(decompress_error(chunk, index, compressor, decoded as usize, len).into());
+        }
+    }
+
+    Ok(buffer)
+}
+
+/// Decompresses the chunk at `index`. Oodle reads the compressor from the chunk
+/// itself, `compressor` from the seek table is only reported if that fails.
+fn decompress_chunk_into(
+    chunk: &[u8],
+    buf: &mut [u8],
+    index: usize,
+    compressor: i32,
+    phase: &DecodeThreadPhase,
+) -> std::io::Result<()> {
+    // DecodeThreadPhase isn't Clone
+    let phase = match phase {
+        DecodeThreadPhase::One => DecodeThreadPhase::One,
+        DecodeThreadPhase::Two => DecodeThreadPhase::Two,
+        DecodeThreadPhase::All => DecodeThreadPhase::All,
+        DecodeThreadPhase::Unthreaded => DecodeThreadPhase::Unthreaded,
+    };
+
+    let expected = buf.len();
+    let decoded = oodle_safe::decompress(chunk, buf, None, None, None, Some(phase))
+        .unwrap_or_else(|code| code as usize);
+    if decoded != expected {
+        return Err(decompress_error(chunk, index, compressor, decoded, expected).into());
+    }
+
+    Ok(())
+}
+
+/// Describes a chunk Oodle decoded `decoded` instead of `expected` bytes of,
+/// with the compressor its header claims next to the seek table's.
+fn decompress_error(
+    chunk: &[u8],
+    index: usize,
+    compressor: i32,
+    decoded: usize,
+    expected: usize,
+) -> BundleError {
+    let detected = unsafe {
+        OodleLZ_GetFirstChunkCompressor(
+            chunk.as_ptr() as *const c_void,
+            chunk.len() as isize,
+            std::ptr::null_mut(),
+        )
+    };
+
+    BundleError::Decompress {
+        chunk: index,
+        compressor,
+        detected,
+        decoded,
+        expected,
+    }
 }
 
 impl<T> Bundle<T>
 where
     T: Into<Arc<[u8]>>,
 {
-    pub fn new(data: T) -> Result<Self, ()> {
+    pub fn new(data: T) -> Result<Self, BundleError> {
+        Self::new_with_chunk_len(data, BLOCK_LEN as usize)
+    }
+
+    /// Same as [`Bundle::new`], but compresses `chunk_len` bytes per seek chunk
+    /// instead of [`BLOCK_LEN`]. Smaller chunks give finer random access with
+    /// [`Bundle::decompress_chunk`], larger ones compress better. Oodle needs a
+    /// power of two of at least [`BLOCK_LEN`].
+    pub fn new_with_chunk_len(data: T, chunk_len: usize) -> Result<Self, BundleError> {
+        Self::new_with(data, Compressor::Hydra, chunk_len)
+    }
+
+    /// Same as [`Bundle::new_with_chunk_len`], but compresses with `compressor`
+    /// instead of Hydra. The game reads every compressor Oodle has.
+    pub fn new_with(
+        data: T,
+        compressor: Compressor,
+        chunk_len: usize,
+    ) -> Result<Self, BundleError> {
+        let compressor: i32 = compressor.into();
+        if !chunk_len.is_power_of_two()
+            || chunk_len < BLOCK_LEN as usize
+            || chunk_len > i32::MAX as usize
+        {
+            return Err(BundleError::ChunkLen(chunk_len as i64));
+        }
+
         let data: Arc<[u8]> = data.into();
 
+        // Same for every chunk
+        let options = SharedOptions(
+            unsafe {
+                let ptr = OodleLZ_CompressOptions_GetDefault(
+                    compressor,
+                    OodleLZ_CompressionLevel_OodleLZ_CompressionLevel_Normal,
+                );
+
+                ptr.as_ref().map(|ptr| *ptr)
+            }
+            .map(|mut options| {
+                options.seekChunkReset = 1;
+                CompressOptions::from(options)
+            }),
+        );
+        let options = &options;
+
+        // Every chunk but the last is `chunk_len` long, so they all need the
+        // same buffer size
+        let buffer_size = |len: usize| unsafe {
+            OodleLZ_GetCompressedBufferSizeNeeded(compressor, len as isize) as usize
+        };
+        let full_size = buffer_size(chunk_len);
+        let last_len = data.len() % chunk_len;
+        let last_size = buffer_size(last_len);
+
         let chunks: Vec<Vec<u8>> = data
-            .par_chunks(BLOCK_LEN as usize)
+            .par_chunks(chunk_len)
             .enumerate()
             .map(|(i, chunk)| {
-                let options = unsafe {
-                    let ptr = OodleLZ_CompressOptions_GetDefault(
-                        OodleLZ_Compressor_OodleLZ_Compressor_Hydra,
-                        OodleLZ_CompressionLevel_OodleLZ_CompressionLevel_Normal,
-                    );
-
-                    ptr.as_ref().map(|ptr| *ptr)
-                }
-                .map(|mut options| {
-                    options.seekChunkReset = 1;
-                    CompressOptions::from(options)
-                });
-                let compressed_size = unsafe {
-                    OodleLZ_GetCompressedBufferSizeNeeded(
-                        OodleLZ_Compressor_OodleLZ_Compressor_Hydra,
-                        chunk.len() as isize,
-                    )
+                let compressed_size = match chunk.len() {
+                    len if len == chunk_len => full_size,
+                    _ => last_size,
                 };
 
                 // FIXME something is wrong here, why doesnt this compress to the same size
-                let mut compressed = vec![0; compressed_size as usize];
+                let mut compressed = vec![0; compressed_size];
                 let compressed_size = oodle_safe::compress(
-                    oodle_safe::Compressor::Hydra,
+                    // Always defined, the id came from a `Compressor`
+                    compressor_from_id(compressor).unwrap(),
                     chunk,
                     &mut compressed,
                     oodle_safe::CompressionLevel::Normal,
-                    options,
+                    options.0,
                     None,
                     None,
                 );
-                if let Ok(size) = compressed_size {
-                    if size < compressed.len() as usize {
-                        compressed.resize(size, 0);
-                    }
-                } else {
-                    eprintln!("[Oodle Error] Index: {i} Size: {}", chunk.len());
+                let size = compressed_size.map_err(|code| BundleError::Compress {
+                    chunk: i,
+                    len: chunk.len(),
+                    code,
+                })?;
+                if size < compressed.len() {
+                    compressed.resize(size, 0);
                 }
-                compressed
+                Ok(compressed)
             })
-            .collect();
+            .collect::<Result<_, BundleError>>()?;
 
         let compressed: Vec<&u8> = chunks.iter().flatten().collect();
 
         let seek_table = unsafe {
             let ptr = OodleLZ_CreateSeekTable(
                 OodleLZSeekTable_Flags_OodleLZSeekTable_Flags_None,
-                BLOCK_LEN as i32,
+                chunk_len as i32,
                 data.as_ptr() as *const _,
                 data.len() as isize,
                 compressed.as_ptr() as *const c_void,
@@ -172,22 +886,27 @@ where
 
             ptr.as_ref().map(|ptr| *ptr)
         }
-        .ok_or(())?;
+        .ok_or(BundleError::SeekTable)?;
 
         let seek_table_size = unsafe {
             OodleLZ_GetSeekTableMemorySizeNeeded(
-                data.chunks(BLOCK_LEN as usize).count() as i32,
+                data.chunks(chunk_len).count() as i32,
                 OodleLZSeekTable_Flags_OodleLZSeekTable_Flags_None,
             )
         };
 
-        // TODO Errors
         if seek_table.totalRawLen != data.len() as i64 {
-            return Err(());
+            return Err(BundleError::UncompressedSize {
+                expected: data.len() as u64,
+                actual: seek_table.totalRawLen as u64,
+            });
         };
 
         if seek_table.totalCompLen != compressed.len() as i64 {
-            return Err(());
+            return Err(BundleError::CompressedSize {
+                expected: compressed.len() as u64,
+                actual: seek_table.totalCompLen as u64,
+            });
         };
 
         let seek_chunk_comp_lens = unsafe {
@@ -235,72 +954,10 @@ where
     type Error = std::io::Error;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let mut offset = 0;
-        let uncompressed_size = read_u32(value, &mut offset);
-        let compressed_size = read_u32(value, &mut offset);
-        let seek_table_size = read_u32(value, &mut offset);
-
-        let mut seek_table = OodleLZ_SeekTable {
-            compressor: read_i32(value, &mut offset),
-            seekChunksIndependent: read_i32(value, &mut offset),
-            totalRawLen: read_i64(value, &mut offset),
-            totalCompLen: read_i64(value, &mut offset),
-            numSeekChunks: read_i32(value, &mut offset),
-            seekChunkLen: read_i32(value, &mut offset),
-            seekChunkCompLens: read_i64(value, &mut offset) as *mut _,
-            rawCRCs: read_i64(value, &mut offset) as *mut _,
-        };
-
-        let seek_chunk_bytes = read_bytes(
-            value,
-            seek_table.numSeekChunks as usize * size_of::<u32>(),
-            &mut offset,
-        );
-
-        let mut seek_chunk_comp_lens: Vec<u32> = seek_chunk_bytes
-            .chunks_exact(size_of::<u32>())
-            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
-            .collect();
-        seek_table.seekChunkCompLens = seek_chunk_comp_lens.as_mut_ptr();
-
-        let chunks = seek_chunk_comp_lens
-            .iter()
-            .map(|&size| {
-                let chunk = value[offset..offset + size as usize].to_vec();
-                offset += size as usize;
-                Arc::from(chunk)
-            })
-            .collect();
-
-        // FIXME read utils need to be Result<_>
-        let raw_crcs = if offset != value.len() {
-            let raw_crcs_bytes = read_bytes(
-                value,
-                seek_table.numSeekChunks as usize * size_of::<u32>(),
-                &mut offset,
-            );
-
-            let mut raw_crcs: Vec<u32> = raw_crcs_bytes
-                .chunks_exact(size_of::<u32>())
-                .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
-                .collect();
+        let bundle = BundleRef::try_from(value)?.into_owned();
+        bundle.verify()?;
 
-            seek_table.rawCRCs = raw_crcs.as_mut_ptr();
-            Some(raw_crcs.into())
-        } else {
-            None
-        };
-
-        Ok(Self {
-            uncompressed_size,
-            compressed_size,
-            seek_table_size,
-            seek_table,
-            seek_chunk_comp_lens: seek_chunk_comp_lens.into(),
-            raw_crcs,
-            chunks,
-            _marker: PhantomData,
-        })
+        Ok(bundle)
     }
 }
 
@@ -338,7 +995,9 @@ impl<T> From<&Bundle<T>> for Arc<[u8]> {
 mod tests {
     use std::time::Instant;
 
-    use super::Bundle;
+    use proptest::{collection::vec, prelude::*};
+
+    use super::{Bundle, Compressor, BLOCK_LEN};
     use crate::index::Index;
 
     #[test]
@@ -369,4 +1028,204 @@ mod tests {
         let file_index = file_bundle.decompress().unwrap().to_vec();
         assert_eq!(index_vec_u8, file_index);
     }
+
+    #[test]
+    fn serialize() {
+        for slice in [
+            include_bytes!("../resources/_.index.bin").as_slice(),
+            include_bytes!("../resources/Shared.bundle.bin").as_slice(),
+        ] {
+            let bundle: Bundle<Vec<u8>> = Bundle::from_slice(slice).unwrap();
+            assert_eq!(bundle.to_vec(), slice);
+        }
+    }
+
+    #[test]
+    fn crcs() {
+        let data: Vec<u8> = (0..600_000u32).map(|i| (i % 251) as u8).collect();
+        let mut bundle: Bundle<Vec<u8>> = Bundle::new(data).unwrap();
+        let crcs = bundle.compute_crcs().unwrap();
+        assert_eq!(crcs.len(), bundle.chunk_count());
+
+        bundle.set_raw_crcs(crcs.clone()).unwrap();
+        let parsed: Bundle<Vec<u8>> = Bundle::from_slice(&bundle.to_vec()).unwrap();
+        assert_eq!(parsed.raw_crcs.as_deref(), Some(crcs.as_slice()));
+        assert!(bundle.set_raw_crcs(vec![]).is_err());
+    }
+
+    #[test]
+    fn chunk_len() {
+        let data: Vec<u8> = (0..600_000u32).map(|i| (i % 251) as u8).collect();
+        let bundle: Bundle<Vec<u8>> = Bundle::new_with_chunk_len(data.clone(), 1 << 19).unwrap();
+        assert_eq!(bundle.chunk_count(), 2);
+        assert_eq!(bundle.decompress().unwrap(), data);
+
+        assert!(Bundle::<Vec<u8>>::new_with_chunk_len(data.clone(), 300_000).is_err());
+        assert!(Bundle::<Vec<u8>>::new_with_chunk_len(data, 1 << 10).is_err());
+    }
+
+    #[test]
+    fn decompress_in() {
+        let slice = include_bytes!("../resources/Shared.bundle.bin");
+        let bundle: Bundle<Vec<u8>> = Bundle::from_slice(slice).unwrap();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            bundle.decompress_in(&pool).unwrap(),
+            bundle.decompress().unwrap()
+        );
+    }
+
+    #[test]
+    fn from_parts() {
+        let data: Vec<u8> = (0..600_000u32).map(|i| (i % 251) as u8).collect();
+        let bundle: Bundle<Vec<u8>> = Bundle::new(data.clone()).unwrap();
+        let chunks = bundle.chunks().to_vec();
+
+        let parts: Bundle<Vec<u8>> = Bundle::from_parts(
+            chunks.clone(),
+            bundle.uncompressed_size(),
+            bundle.chunk_len(),
+            Compressor::Hydra,
+            true,
+            None,
+        )
+        .unwrap();
+        assert_eq!(parts.decompress().unwrap(), data);
+        assert_eq!(parts.size(), bundle.size());
+
+        let missing = Bundle::<Vec<u8>>::from_parts(
+            chunks[1..].to_vec(),
+            bundle.uncompressed_size(),
+            bundle.chunk_len(),
+            Compressor::Hydra,
+            true,
+            None,
+        );
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn new_with() {
+        let data: Vec<u8> = (0..600_000u32).map(|i| (i % 251) as u8).collect();
+        let bundle: Bundle<Vec<u8>> =
+            Bundle::new_with(data.clone(), Compressor::Kraken, BLOCK_LEN as usize).unwrap();
+        assert!(matches!(bundle.compressor(), Some(Compressor::Kraken)));
+        assert_eq!(bundle.decompress().unwrap(), data);
+    }
+
+    #[test]
+    fn verify() {
+        let index = include_bytes!("../resources/_.index.bin");
+        let bundle: Bundle<Index> = Bundle::from_slice(index).unwrap();
+        bundle.verify().unwrap();
+
+        // Bump compressed_size so it disagrees with the seek chunk lengths
+        let mut corrupt = index.to_vec();
+        corrupt[4] = corrupt[4].wrapping_add(1);
+        assert!(Bundle::<Index>::from_slice(&corrupt).is_err());
+    }
+
+    #[test]
+    fn decompress_range() {
+        let data: Vec<u8> = (0..600_000u32).map(|i| (i % 251) as u8).collect();
+        let bundle: Bundle<Vec<u8>> = Bundle::new(data.clone()).unwrap();
+        let chunk_len = bundle.chunk_len();
+
+        for (offset, len) in [(0, 10), (chunk_len - 5, 10), (1000, 300_000), (599_990, 10)] {
+            let range = bundle.decompress_range(offset, len).unwrap();
+            assert_eq!(range, data[offset..offset + len]);
+        }
+        assert!(bundle.decompress_range(600_000, 0).unwrap().is_empty());
+        assert_eq!(
+            bundle.decompress_range(599_990, 11).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn view() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let bundle: Bundle<Vec<u8>> = Bundle::new(data.clone()).unwrap();
+        assert_eq!(bundle.decompress_borrowed().unwrap(), data);
+
+        let mut buffer = vec![];
+        let view: &[u8] = bundle.decompress_view(&mut buffer).unwrap();
+        assert_eq!(view, data);
+    }
+
+    #[test]
+    fn empty() {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // uncompressed_size
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // compressed_size
+        bytes.extend_from_slice(&48u32.to_le_bytes()); // seek_table_size
+        bytes.extend_from_slice(&8i32.to_le_bytes()); // Kraken
+        bytes.extend_from_slice(&1i32.to_le_bytes()); // seekChunksIndependent
+        bytes.extend_from_slice(&0i64.to_le_bytes()); // totalRawLen
+        bytes.extend_from_slice(&0i64.to_le_bytes()); // totalCompLen
+        bytes.extend_from_slice(&0i32.to_le_bytes()); // numSeekChunks
+        bytes.extend_from_slice(&262144i32.to_le_bytes()); // seekChunkLen
+        bytes.extend_from_slice(&[0; 16]); // pointers
+
+        let bundle: Bundle<Vec<u8>> = Bundle::from_slice(&bytes).unwrap();
+        assert!(bundle.is_empty());
+        assert_eq!(bundle.chunk_count(), 0);
+        assert!(bundle.decompress().unwrap().is_empty());
+        assert!(bundle.decompress_sequential().unwrap().is_empty());
+        assert_eq!(bundle.to_vec(), bytes);
+
+        // A seek_table_size that doesn't match the tables is rejected
+        bytes[8..12].copy_from_slice(&52u32.to_le_bytes());
+        let error = Bundle::<Vec<u8>>::from_slice(&bytes).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_bytes(bytes in vec(any::<u8>(), 0..512)) {
+            if let Ok(bundle) = Bundle::<Vec<u8>>::try_from(bytes.as_slice()) {
+                decompress_all(&bundle);
+            }
+        }
+
+        /// Garbage that starts out as a real bundle gets past the header
+        /// checks and into the seek table and chunks.
+        #[test]
+        fn corrupted_bundle(
+            edits in vec((any::<prop::sample::Index>(), any::<u8>()), 1..8),
+            truncate in any::<prop::sample::Index>(),
+        ) {
+            let mut bytes = include_bytes!("../resources/Shared.bundle.bin").to_vec();
+            for (at, byte) in edits {
+                let at = at.index(bytes.len());
+                bytes[at] = byte;
+            }
+            bytes.truncate(truncate.index(bytes.len() + 1));
+
+            // Whatever parses has to survive being written back out
+            if let Ok(bundle) = Bundle::<Vec<u8>>::try_from(bytes.as_slice()) {
+                let written = bundle.to_vec();
+                let reparsed = Bundle::<Vec<u8>>::try_from(written.as_slice()).unwrap();
+                prop_assert_eq!(reparsed.to_vec(), written);
+                decompress_all(&bundle);
+            }
+        }
+    }
+
+    /// Whatever parses has to be safe to decompress too. Garbage chunks fail
+    /// to decode, but mustn't panic or make an absurd allocation.
+    fn decompress_all(bundle: &Bundle<Vec<u8>>) {
+        let _ = bundle.decompress();
+        if bundle.chunk_count() > 0 {
+            let _ = bundle.decompress_chunk(0);
+        }
+        let size = bundle.uncompressed_size() as usize;
+        let len = size.min(BLOCK_LEN as usize + 1);
+        let _ = bundle.decompress_range(0, len);
+        let _ = bundle.decompress_range(size - len, len);
+    }
 }
@@ -0,0 +1,13 @@
+//! The types most users of the crate need, for a single glob import:
+//!
+//! ```
+//! use bundle::prelude::*;
+//! ```
+
+pub use crate::{
+    index::{
+        BundleRecord, ExtractError, ExtractOptions, Extraction, Failed, FileRecord, Index,
+        IndexBuilder, RecordError,
+    },
+    Bundle, BundleError, BundleRef,
+};
@@ -0,0 +1,96 @@
+//! `wasm-bindgen` exports for looking up and reading files in the browser.
+//!
+//! Lookups only parse the decompressed index, so JS can work out which bundle
+//! to fetch for a path. [`WasmIndex::new`] and [`WasmIndex::read_file`] call
+//! into Oodle and need a wasm build of it to link.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{index::Index, Bundle};
+
+#[wasm_bindgen]
+pub struct WasmIndex(Index);
+
+/// Where a file's bytes live: `offset..offset + size` in the decompressed
+/// `Bundles2/{bundle}.bundle.bin`
+#[wasm_bindgen]
+pub struct FileLocation {
+    bundle: String,
+    offset: u32,
+    size: u32,
+}
+
+#[wasm_bindgen]
+impl FileLocation {
+    #[wasm_bindgen(getter)]
+    pub fn bundle(&self) -> String {
+        self.bundle.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+#[wasm_bindgen]
+impl WasmIndex {
+    /// Decompresses and parses the contents of `_.index.bin`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(index_bin: &[u8]) -> Result<WasmIndex, JsError> {
+        let bundle: Bundle<Index> = Bundle::from_slice(index_bin)?;
+        Ok(Self(bundle.decompress()?))
+    }
+
+    /// Parses an index that was already decompressed, without Oodle.
+    #[wasm_bindgen(js_name = fromDecompressed)]
+    pub fn from_decompressed(bytes: &[u8]) -> Result<WasmIndex, JsError> {
+        Ok(Self(Index::try_from(bytes)?))
+    }
+
+    #[wasm_bindgen(js_name = totalFiles)]
+    pub fn total_files(&self) -> usize {
+        self.0.total_files()
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.0.contains(path)
+    }
+
+    /// Finds the bundle and byte range holding `path`, ignoring case.
+    pub fn locate(&self, path: &str) -> Option<FileLocation> {
+        let record = self.0.file_by_path(path)?;
+        let bundle = self.0.bundle(record.bundle_idx())?;
+
+        Some(FileLocation {
+            bundle: bundle.path().to_string(),
+            offset: record.offset,
+            size: record.size,
+        })
+    }
+
+    /// Reads `path` out of `bundle_bin`, the fetched bundle that
+    /// [`WasmIndex::locate`] pointed at. Returned to JS as a `Uint8Array`.
+    #[wasm_bindgen(js_name = readFile)]
+    pub fn read_file(&self, bundle_bin: &[u8], path: &str) -> Result<Vec<u8>, JsError> {
+        let location = self
+            .locate(path)
+            .ok_or_else(|| JsError::new(&format!("{path} is not in the index")))?;
+
+        // Only the chunks the file overlaps are decompressed
+        let bundle: Bundle<Vec<u8>> = Bundle::from_slice(bundle_bin)?;
+        bundle
+            .decompress_range(location.offset as usize, location.size as usize)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::InvalidInput => {
+                    JsError::new(&format!("{path} is out of the bundle's bounds"))
+                }
+                _ => e.into(),
+            })
+    }
+}
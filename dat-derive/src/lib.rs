@@ -0,0 +1,61 @@
+//! Derive macro for `dat::FromRow`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields};
+
+/// Reads a struct out of a row, one field after the other in declaration
+/// order. Every field type has to implement `dat::FromColumn`.
+#[proc_macro_derive(FromRow)]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "FromRow can only be derived for structs",
+        ));
+    };
+
+    let types: Vec<_> = data.fields.iter().map(|field| &field.ty).collect();
+    let locals: Vec<_> = (0..types.len())
+        .map(|i| format_ident!("field_{i}"))
+        .collect();
+    let construct = match &data.fields {
+        Fields::Named(fields) => {
+            let names = fields.named.iter().map(|field| &field.ident);
+            quote!(Self { #(#names: #locals),* })
+        }
+        Fields::Unnamed(_) => quote!(Self(#(#locals),*)),
+        Fields::Unit => quote!(Self),
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::dat::FromRow for #name #ty_generics #where_clause {
+            const WIDTH: usize = 0 #(+ <#types as ::dat::FromColumn>::WIDTH)*;
+
+            fn from_row(row: &[u8], variable: &[u8]) -> ::core::option::Option<Self> {
+                let mut offset = 0;
+                #(
+                    let width = <#types as ::dat::FromColumn>::WIDTH;
+                    let #locals = <#types as ::dat::FromColumn>::from_column(
+                        row.get(offset..offset + width)?,
+                        variable,
+                    )?;
+                    offset += width;
+                )*
+                let _ = offset;
+                ::core::option::Option::Some(#construct)
+            }
+        }
+    })
+}
@@ -0,0 +1,224 @@
+//! Editing tables, see [`Datc64Buf`].
+
+use crate::{
+    schema::Column,
+    value::{self, push, push_string, Value},
+    DatError, StringRef, SEPERATOR,
+};
+
+/// An owned table whose rows can be changed and added to, made with
+/// [`Datc64::to_buf`](crate::Datc64::to_buf) and written back with
+/// [`Datc64Buf::to_bytes`].
+///
+/// Strings and arrays are never rewritten in place, new ones are appended to
+/// the variable data and the row is pointed at their offset. What they
+/// replace is left where it is, other rows can point at the same data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Datc64Buf {
+    rows: Vec<Vec<u8>>,
+    /// Width of every row, `None` until the first row of an empty table
+    row_width: Option<usize>,
+    /// Starts with the separator, like [`Datc64::variable_data`](crate::Datc64::variable_data)
+    variable_data: Vec<u8>,
+}
+
+impl Datc64Buf {
+    pub(crate) fn new(
+        rows: Vec<Vec<u8>>,
+        row_width: Option<usize>,
+        variable_data: Vec<u8>,
+    ) -> Self {
+        // Offsets are relative to the separator, tables without one get it
+        // before anything is appended
+        let variable_data = match variable_data.is_empty() {
+            true => SEPERATOR.to_vec(),
+            false => variable_data,
+        };
+
+        Self {
+            rows,
+            row_width,
+            variable_data,
+        }
+    }
+
+    /// Number of rows in the table
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn row(&self, index: usize) -> Option<&[u8]> {
+        self.rows.get(index).map(Vec::as_slice)
+    }
+
+    /// The row at `index` to change in place. Offsets written into it have to
+    /// point into [`Datc64Buf::variable_data`], see [`Datc64Buf::push_string`]
+    /// and [`Datc64Buf::push_data`].
+    pub fn row_mut(&mut self, index: usize) -> Option<&mut [u8]> {
+        self.rows.get_mut(index).map(Vec::as_mut_slice)
+    }
+
+    pub fn variable_data(&self) -> &[u8] {
+        &self.variable_data
+    }
+
+    /// Replaces the row at `index`, which has to be as wide as the others.
+    pub fn set_row(&mut self, index: usize, row: &[u8]) -> Result<(), DatError> {
+        self.check_width(row)?;
+        let len = self.rows.len();
+        let old = self
+            .rows
+            .get_mut(index)
+            .ok_or(DatError::NoRow { index, len })?;
+        old.copy_from_slice(row);
+        Ok(())
+    }
+
+    /// Adds a row to the end of the table, returning its index. The first row
+    /// of an empty table without a known width sets it.
+    pub fn push_row(&mut self, row: &[u8]) -> Result<usize, DatError> {
+        self.check_width(row)?;
+        self.row_width = Some(row.len());
+        self.rows.push(row.to_vec());
+        Ok(self.rows.len() - 1)
+    }
+
+    /// Writes `value` into `column` of the row at `index`, e.g. a column of
+    /// [`TableSchema::column`](crate::TableSchema::column). Strings and array
+    /// elements are appended to the variable data first.
+    pub fn set(&mut self, index: usize, column: &Column, value: &Value) -> Result<(), DatError> {
+        let len = self.rows.len();
+        let row = self
+            .rows
+            .get_mut(index)
+            .ok_or(DatError::NoRow { index, len })?;
+        value::write(row, column, value, &mut self.variable_data)
+    }
+
+    /// The value of `column` in the row at `index`, like
+    /// [`Datc64::get`](crate::Datc64::get).
+    pub fn get(&self, index: usize, column: &Column) -> Option<Value> {
+        value::read(self.row(index)?, column, &self.variable_data)
+    }
+
+    /// Appends a null-terminated UTF-16 string to the variable data, for
+    /// pointing a row's string column at.
+    pub fn push_string(&mut self, s: &str) -> StringRef {
+        StringRef(push_string(&mut self.variable_data, s))
+    }
+
+    /// Appends raw bytes to the variable data, like the elements of an array,
+    /// returning their offset.
+    pub fn push_data(&mut self, data: &[u8]) -> u64 {
+        push(&mut self.variable_data, data)
+    }
+
+    /// Serializes the table in the layout read by `TryFrom<&[u8]>`, like
+    /// [`Datc64::to_bytes`](crate::Datc64::to_bytes).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let rows: usize = self.rows.iter().map(Vec::len).sum();
+        let mut data = Vec::with_capacity(4 + rows + self.variable_data.len());
+
+        data.extend_from_slice(&(self.rows.len() as u32).to_le_bytes());
+        for row in &self.rows {
+            data.extend_from_slice(row);
+        }
+        data.extend_from_slice(&self.variable_data);
+
+        data
+    }
+
+    fn check_width(&self, row: &[u8]) -> Result<(), DatError> {
+        match self.row_width {
+            Some(expected) if expected != row.len() => Err(DatError::RowLen {
+                expected,
+                actual: row.len(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DatError, Datc64, Schema, StringRef, Value, SEPERATOR};
+
+    #[test]
+    fn edit() {
+        let schema = Schema::from_json(crate::schema::tests::SCHEMA).unwrap();
+        let mods = schema.table("Mods").unwrap();
+        let column = |name| mods.column(name).unwrap();
+
+        let mut row = vec![0; mods.row_width()];
+        row[..8].copy_from_slice(&8u64.to_le_bytes()); // Id
+        row[12..16].copy_from_slice(&68u32.to_le_bytes()); // Level
+        let mut data = 2u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&row);
+        data.extend_from_slice(&row);
+        data.extend_from_slice(&SEPERATOR);
+        for unit in "Str\0".encode_utf16() {
+            data.extend_from_slice(&unit.to_le_bytes());
+        }
+
+        let table: Datc64<()> = Datc64::with_schema(&data, mods).unwrap();
+        let mut buf = table.to_buf();
+        buf.set(0, column("Id"), &Value::String("Strength".to_string()))
+            .unwrap();
+        buf.set(0, column("Level"), &Value::U32(70)).unwrap();
+        let stats = Value::Array(vec![Value::ForeignRow(Some(1)), Value::ForeignRow(None)]);
+        buf.set(1, column("Stats"), &stats).unwrap();
+        let roll = Value::Interval(Box::new((Value::I32(1), Value::I32(2))));
+        buf.set(1, column("Roll"), &roll).unwrap();
+
+        // Appended after the original string, which the second row still uses
+        let bytes = buf.to_bytes();
+        let edited: Datc64<()> = Datc64::with_schema(&bytes, mods).unwrap();
+        assert_eq!(edited.len(), 2);
+        assert_eq!(edited.get(0, "Id"), Some(Value::String("Strength".into())));
+        assert_eq!(edited.get(0, "Level"), Some(Value::U32(70)));
+        assert_eq!(edited.get(1, "Id"), Some(Value::String("Str".into())));
+        assert_eq!(edited.get(1, "Stats"), Some(stats));
+        assert_eq!(edited.get(1, "Roll"), Some(roll));
+        assert_eq!(edited.to_buf(), buf);
+
+        assert_eq!(
+            buf.set(0, column("Level"), &Value::I32(1)),
+            Err(DatError::ColumnValue {
+                offset: 12,
+                ty: crate::ColumnType::U32
+            })
+        );
+        assert_eq!(
+            buf.set(2, column("Level"), &Value::U32(1)),
+            Err(DatError::NoRow { index: 2, len: 2 })
+        );
+
+        let index = buf.push_row(&row).unwrap();
+        let StringRef(offset) = buf.push_string("Dex");
+        buf.row_mut(index).unwrap()[..8].copy_from_slice(&offset.to_le_bytes());
+        assert_eq!(
+            buf.get(index, column("Id")),
+            Some(Value::String("Dex".into()))
+        );
+        assert_eq!(
+            buf.set_row(0, &[0; 4]),
+            Err(DatError::RowLen {
+                expected: mods.row_width(),
+                actual: 4
+            })
+        );
+
+        // Tables without a separator get one before the first string
+        let count = 0u32.to_le_bytes();
+        let mut empty = Datc64::<()>::try_from(&count[..]).unwrap().to_buf();
+        assert_eq!(empty.push_string("a"), StringRef(8));
+        empty.push_row(&[1, 2]).unwrap();
+        let bytes = empty.to_bytes();
+        let table: Datc64<[u8; 2]> = Datc64::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(table.row(0), Some(&[1, 2][..]));
+    }
+}
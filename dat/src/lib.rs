@@ -1,34 +1,426 @@
-use std::{any::type_name, marker::PhantomData};
+use std::{any::type_name, fmt, iter::Copied, marker::PhantomData, slice};
+
+// Lets `#[derive(FromRow)]` refer to `::dat` from inside this crate
+extern crate self as dat;
+
+mod buf;
+mod row;
+mod schema;
+mod value;
+
+pub use buf::Datc64Buf;
+pub use dat_derive::FromRow;
+pub use row::{ForeignRowRef, FromColumn, FromRow, RowRef, StringRef};
+pub use schema::{Column, ColumnType, Schema, SchemaError, TableSchema};
+pub use value::Value;
 
 const SEPERATOR: [u8; 8] = [0xBB; 8];
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatError {
+    /// Shorter than the row count at the start of every table
+    Truncated,
+    /// The fixed section doesn't split into `count` rows of the same width
+    UnevenRows { len: usize, count: usize },
+    /// Rows are a different width than the type they're read as
+    RowWidth {
+        expected: usize,
+        actual: usize,
+        ty: &'static str,
+    },
+    /// A row that isn't as wide as the table's other rows
+    RowLen { expected: usize, actual: usize },
+    /// There's no row at `index`, the table has `len`
+    NoRow { index: usize, len: usize },
+    /// A value that doesn't fit the column it's written to
+    ColumnValue { offset: usize, ty: ColumnType },
+}
+
+impl fmt::Display for DatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "table is too short for a row count"),
+            Self::UnevenRows { len, count } => {
+                write!(
+                    f,
+                    "{len} bytes of fixed data don't divide into {count} rows"
+                )
+            }
+            Self::RowWidth {
+                expected,
+                actual,
+                ty,
+            } => write!(
+                f,
+                "row data length {actual} does not match struct size {expected} for {ty}"
+            ),
+            Self::RowLen { expected, actual } => {
+                write!(f, "row is {actual} bytes, the table's rows are {expected}")
+            }
+            Self::NoRow { index, len } => write!(f, "no row {index}, the table has {len}"),
+            Self::ColumnValue { offset, ty } => {
+                write!(f, "value doesn't fit the {ty:?} column at offset {offset}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatError {}
+
 pub struct Datc64<'a, T> {
     row_bytes: Vec<&'a [u8]>,
+    /// Everything from the separator on. Offsets in the rows are relative to
+    /// its start, so the first byte of data after the separator is at 8.
+    variable_data: &'a [u8],
+    /// Column layout for looking up values by name, see [`Datc64::with_schema`]
+    schema: Option<&'a TableSchema>,
     _marker: PhantomData<T>,
 }
 
-impl<T> From<&[u8]> for Datc64<'_, T> {
-    fn from(value: &[u8]) -> Self {
-        let count = u32::from_le_bytes(value[..4].try_into().unwrap()) as usize;
-        let data_offset = value.windows(8).position(|win| win == SEPERATOR);
+impl<'a, T> Datc64<'a, T> {
+    /// Parses a table whose columns are laid out by `schema`, so they can be
+    /// read by name with [`Datc64::get`].
+    pub fn with_schema(value: &'a [u8], schema: &'a TableSchema) -> Result<Self, DatError> {
+        let row_width = Some(schema.row_width());
+        Ok(Self {
+            schema: Some(schema),
+            ..Self::parse(value, row_width)?
+        })
+    }
+
+    /// The value of `column` in the row at `index`. `None` without a schema,
+    /// for unknown columns and rows, or if the value points out of bounds.
+    pub fn get(&self, index: usize, column: &str) -> Option<Value> {
+        let column = self.schema?.column(column)?;
+        value::read(self.row(index)?, column, self.variable_data)
+    }
+
+    /// The row at `index` read into `R`, see [`FromRow`]. `None` for unknown
+    /// rows, rows narrower than `R` or if a field points out of bounds.
+    pub fn get_row_typed<R: FromRow>(&self, index: usize) -> Option<R> {
+        let row = self.row(index)?;
+        R::from_row(row.get(..R::WIDTH)?, self.variable_data)
+    }
+
+    /// Number of rows in the table
+    pub fn len(&self) -> usize {
+        self.row_bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.row_bytes.is_empty()
+    }
+
+    /// The fixed-size bytes of the row at `index`, `None` if out of bounds.
+    pub fn row(&self, index: usize) -> Option<&'a [u8]> {
+        self.row_bytes.get(index).copied()
+    }
+
+    /// The variable-length section that strings and arrays point into,
+    /// starting with the separator. Empty when the table doesn't have one.
+    pub fn variable_data(&self) -> &'a [u8] {
+        self.variable_data
+    }
 
-        let rows_data = data_offset.map_or(&[][..], |i| &value[i..]);
-        let row_end_index = data_offset.map_or(value.len(), |i| i);
+    /// The fixed-size bytes of every row, in table order.
+    pub fn iter(&self) -> Copied<slice::Iter<'_, &'a [u8]>> {
+        self.row_bytes.iter().copied()
+    }
 
-        let row_length = rows_data.len() / count;
-        let mut row_bytes = Vec::with_capacity(row_length);
+    /// Serializes the table in the layout read by `TryFrom<&[u8]>`: the row count,
+    /// the fixed rows, the separator and the variable data. String and array
+    /// offsets are relative to the separator, so they stay valid as long as the
+    /// variable data is written back unchanged after it. Edit a copy made with
+    /// [`Datc64::to_buf`] to change rows.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let rows: usize = self.row_bytes.iter().map(|row| row.len()).sum();
+        let variable = self
+            .variable_data
+            .get(SEPERATOR.len()..)
+            .unwrap_or_default();
+        let mut data = Vec::with_capacity(4 + rows + SEPERATOR.len() + variable.len());
 
-        for i in 0..count {
-            let row_data = &rows_data[(i * row_length)..((i + 1) * row_length)];
-            row_bytes.push(row_data);
+        data.extend_from_slice(&(self.row_bytes.len() as u32).to_le_bytes());
+        for row in &self.row_bytes {
+            data.extend_from_slice(row);
         }
+        data.extend_from_slice(&SEPERATOR);
+        data.extend_from_slice(variable);
+
+        data
+    }
+
+    /// An owned copy of the table whose rows can be edited, see [`Datc64Buf`].
+    pub fn to_buf(&self) -> Datc64Buf {
+        let row_width = self
+            .row_bytes
+            .first()
+            .map(|row| row.len())
+            .or(self.schema.map(TableSchema::row_width))
+            .or((size_of::<T>() != 0).then_some(size_of::<T>()));
+
+        Datc64Buf::new(
+            self.row_bytes.iter().map(|row| row.to_vec()).collect(),
+            row_width,
+            self.variable_data.to_vec(),
+        )
+    }
+}
+
+impl<T> PartialEq for Datc64<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.row_bytes == other.row_bytes && self.variable_data == other.variable_data
+    }
+}
+
+impl<T> fmt::Debug for Datc64<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Datc64")
+            .field("rows", &self.row_bytes.len())
+            .field("variable_data", &self.variable_data.len())
+            .finish()
+    }
+}
+
+impl<'a, 'b, T> IntoIterator for &'b Datc64<'a, T> {
+    type Item = &'a [u8];
+    type IntoIter = Copied<slice::Iter<'b, &'a [u8]>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> TryFrom<&'a [u8]> for Datc64<'a, T> {
+    type Error = DatError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let row_width = (size_of::<T>() != 0).then_some(size_of::<T>());
+        Self::parse(value, row_width)
+    }
+}
+
+impl<'a, T> Datc64<'a, T> {
+    fn parse(value: &'a [u8], row_width: Option<usize>) -> Result<Self, DatError> {
+        let count = value.get(..4).ok_or(DatError::Truncated)?;
+        let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+        let data_offset = find_separator(value, count, row_width);
+
+        let row_end_index = data_offset.unwrap_or(value.len());
+        let rows_data = &value[4..row_end_index];
+        let variable_data = &value[row_end_index..];
+
+        // Tables without rows are legal, their width can't be checked
+        let row_bytes = if count == 0 {
+            vec![]
+        } else {
+            if !rows_data.len().is_multiple_of(count) {
+                return Err(DatError::UnevenRows {
+                    len: rows_data.len(),
+                    count,
+                });
+            }
+
+            let row_length = rows_data.len() / count;
+            // Zero-sized `T`s are untyped tables
+            if size_of::<T>() != 0 && row_length != size_of::<T>() {
+                return Err(DatError::RowWidth {
+                    expected: size_of::<T>(),
+                    actual: row_length,
+                    ty: type_name::<T>(),
+                });
+            }
+            (0..count)
+                .map(|i| &rows_data[(i * row_length)..((i + 1) * row_length)])
+                .collect()
+        };
+
+        Ok(Self {
+            row_bytes,
+            variable_data,
+            schema: None,
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// Finds the separator between the fixed rows and the variable data. Fixed
+/// data can contain `0xBB` runs of its own, so only offsets where the rows end
+/// are considered: exactly `count * row_width` bytes in when the width is
+/// known, otherwise the first offset the rows divide evenly into. Tables
+/// without variable data end with the separator, or lack it entirely.
+fn find_separator(value: &[u8], count: usize, row_width: Option<usize>) -> Option<usize> {
+    let is_separator = |offset: usize| value.get(offset..offset + 8) == Some(&SEPERATOR[..]);
+
+    match row_width {
+        Some(width) => {
+            let offset = 4 + count * width;
+            is_separator(offset).then_some(offset)
+        }
+        None => (4..value.len().saturating_sub(7))
+            .filter(|offset| count == 0 || (offset - 4) % count == 0)
+            .find(|&offset| is_separator(offset)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DatError, Datc64, ForeignRowRef, FromRow, Schema, StringRef, Value, SEPERATOR};
+
+    #[test]
+    fn separator() {
+        // The second row is all 0xBB, only the real separator is at 4 + 2 * 8
+        let mut data = 2u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&[1; 8]);
+        data.extend_from_slice(&SEPERATOR);
+        data.extend_from_slice(&SEPERATOR);
+        data.extend_from_slice(b"data");
+
+        let table: Datc64<[u8; 8]> = Datc64::try_from(data.as_slice()).unwrap();
+        assert_eq!(table.row(1), Some(&SEPERATOR[..]));
+        assert_eq!(&table.variable_data()[8..], b"data");
+
+        // Without variable data the separator is the last thing in the table
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0xBB, 0xBB, 1]);
+        data.extend_from_slice(&SEPERATOR);
+
+        let table: Datc64<()> = Datc64::try_from(data.as_slice()).unwrap();
+        assert_eq!(table.row(0), Some(&[0xBB, 0xBB, 1][..]));
+        assert_eq!(table.variable_data(), SEPERATOR);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut data = 2u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&[8, 0, 0, 0, 12, 0, 0, 0]);
+        data.extend_from_slice(&SEPERATOR);
+        data.extend_from_slice(b"abcdefgh");
+
+        let table: Datc64<[u8; 4]> = Datc64::try_from(data.as_slice()).unwrap();
+        let bytes = table.to_bytes();
+        assert_eq!(bytes, data);
+        assert_eq!(Datc64::try_from(bytes.as_slice()).unwrap(), table);
+    }
+
+    #[test]
+    fn schema() {
+        let schema = Schema::from_json(crate::schema::tests::SCHEMA).unwrap();
+        let mods = schema.table("Mods").unwrap();
+
+        let mut row = vec![];
+        row.extend_from_slice(&8u64.to_le_bytes()); // Id
+        row.extend_from_slice(&(-1i32).to_le_bytes());
+        row.extend_from_slice(&68u32.to_le_bytes()); // Level
+        row.extend_from_slice(&2u64.to_le_bytes()); // Stats
+        row.extend_from_slice(&16u64.to_le_bytes());
+        row.extend_from_slice(&5i32.to_le_bytes()); // Roll
+        row.extend_from_slice(&10i32.to_le_bytes());
+        row.extend_from_slice(&[0; 16]); // Tags
+
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&row);
+        data.extend_from_slice(&SEPERATOR);
+        for unit in "Str\0".encode_utf16() {
+            data.extend_from_slice(&unit.to_le_bytes());
+        }
+        data.extend_from_slice(&[3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&[0xFE; 16]);
+
+        let table: Datc64<()> = Datc64::with_schema(&data, mods).unwrap();
+        assert_eq!(table.get(0, "Id"), Some(Value::String("Str".to_string())));
+        assert_eq!(table.get(0, "level"), Some(Value::U32(68)));
+        assert_eq!(
+            table.get(0, "Stats"),
+            Some(Value::Array(vec![
+                Value::ForeignRow(Some(3)),
+                Value::ForeignRow(None)
+            ]))
+        );
+        assert_eq!(
+            table.get(0, "Roll"),
+            Some(Value::Interval(Box::new((Value::I32(5), Value::I32(10)))))
+        );
+        assert_eq!(table.get(0, "Tags"), Some(Value::Array(vec![])));
+        assert_eq!(table.get(1, "Id"), None);
+        assert_eq!(table.get(0, "Missing"), None);
+
+        #[derive(FromRow)]
+        struct Mod {
+            id: StringRef,
+            _unknown: i32,
+            level: u32,
+            stats: Vec<ForeignRowRef>,
+            roll: (i32, i32),
+        }
+
+        let row: Mod = table.get_row_typed(0).unwrap();
+        assert_eq!(Mod::WIDTH, mods.row_width() - 16);
+        assert_eq!(
+            row.id.resolve(table.variable_data()).as_deref(),
+            Some("Str")
+        );
+        assert_eq!(row.level, 68);
+        assert_eq!(row.stats, [ForeignRowRef(Some(3)), ForeignRowRef(None)]);
+        assert_eq!(row.roll, (5, 10));
+        assert!(table.get_row_typed::<Mod>(1).is_none());
+
+        #[derive(FromRow)]
+        struct Id(String);
+        let Id(id) = table.get_row_typed(0).unwrap();
+        assert_eq!(id, "Str");
+    }
+
+    #[test]
+    fn rows() {
+        let mut data = 3u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&[1, 1, 2, 2, 3, 3]);
+        data.extend_from_slice(&SEPERATOR);
+
+        let table: Datc64<[u8; 2]> = Datc64::try_from(data.as_slice()).unwrap();
+        assert_eq!(table.len(), 3);
+        assert!(!table.is_empty());
+        assert_eq!(table.row(1), Some(&[2, 2][..]));
+        assert_eq!(table.row(3), None);
+
+        let rows: Vec<_> = (&table).into_iter().collect();
+        assert_eq!(rows, [[1, 1], [2, 2], [3, 3]]);
+    }
+
+    #[test]
+    fn malformed() {
+        let empty = 0u32.to_le_bytes();
+        let table: Datc64<[u8; 4]> = Datc64::try_from(&empty[..]).unwrap();
+        assert!(table.is_empty());
+
+        let mut data = 0u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&SEPERATOR);
+        let table: Datc64<()> = Datc64::try_from(data.as_slice()).unwrap();
+        assert!(table.is_empty());
+        assert_eq!(table.variable_data(), SEPERATOR);
+
+        // Without a separator everything after the count is fixed data
+        let mut data = 2u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(
+            Datc64::<()>::try_from(data.as_slice()),
+            Err(DatError::UnevenRows { len: 3, count: 2 })
+        );
+
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&[1, 2, 3]);
+        assert!(matches!(
+            Datc64::<[u8; 4]>::try_from(data.as_slice()),
+            Err(DatError::RowWidth {
+                expected: 4,
+                actual: 3,
+                ..
+            })
+        ));
 
-        assert!(
-            count < size_of::<T>(),
-            "Row data length {count} does not match struct size {} for {}",
-            size_of::<T>(),
-            type_name::<T>()
+        assert_eq!(
+            Datc64::<()>::try_from(&[1, 0][..]),
+            Err(DatError::Truncated)
         );
-        todo!()
     }
 }
@@ -0,0 +1,133 @@
+//! Reading whole rows into structs, see [`FromRow`].
+
+use crate::value::{array, string, u64_at, NULL_ROW};
+
+/// A row struct whose fields are read one after the other in declaration
+/// order. Derive it with `#[derive(FromRow)]` for structs whose fields all
+/// implement [`FromColumn`].
+pub trait FromRow: Sized {
+    /// Bytes of the row the struct covers. Rows can be wider, the columns
+    /// after the last field are ignored.
+    const WIDTH: usize;
+
+    /// Reads the struct from the start of `row`, following strings and arrays
+    /// into `variable`. `None` if anything points out of bounds.
+    fn from_row(row: &[u8], variable: &[u8]) -> Option<Self>;
+}
+
+/// A value that takes up a fixed number of bytes in a row.
+pub trait FromColumn: Sized {
+    const WIDTH: usize;
+
+    /// Reads the value from exactly [`FromColumn::WIDTH`] bytes.
+    fn from_column(bytes: &[u8], variable: &[u8]) -> Option<Self>;
+}
+
+/// Offset of a string in the variable data, for reading it only when needed
+/// with [`StringRef::resolve`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StringRef(pub u64);
+
+impl StringRef {
+    pub fn resolve(self, variable: &[u8]) -> Option<String> {
+        string(variable, self.0 as usize)
+    }
+}
+
+/// Index of a row in the same table, `None` for a null reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RowRef(pub Option<u64>);
+
+/// Index of a row in the referenced table, `None` for a null reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ForeignRowRef(pub Option<u64>);
+
+macro_rules! from_le_bytes {
+    ($($ty:ty),*) => {
+        $(
+            impl FromColumn for $ty {
+                const WIDTH: usize = size_of::<$ty>();
+
+                fn from_column(bytes: &[u8], _: &[u8]) -> Option<Self> {
+                    array(bytes).map(<$ty>::from_le_bytes)
+                }
+            }
+        )*
+    };
+}
+
+from_le_bytes!(i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
+
+impl FromColumn for bool {
+    const WIDTH: usize = 1;
+
+    fn from_column(bytes: &[u8], _: &[u8]) -> Option<Self> {
+        Some(*bytes.first()? != 0)
+    }
+}
+
+impl FromColumn for String {
+    const WIDTH: usize = 8;
+
+    fn from_column(bytes: &[u8], variable: &[u8]) -> Option<Self> {
+        StringRef::from_column(bytes, variable)?.resolve(variable)
+    }
+}
+
+impl FromColumn for StringRef {
+    const WIDTH: usize = 8;
+
+    fn from_column(bytes: &[u8], _: &[u8]) -> Option<Self> {
+        u64_at(bytes, 0).map(Self)
+    }
+}
+
+impl FromColumn for RowRef {
+    const WIDTH: usize = 8;
+
+    fn from_column(bytes: &[u8], _: &[u8]) -> Option<Self> {
+        let index = u64_at(bytes, 0)?;
+        Some(Self((index != NULL_ROW).then_some(index)))
+    }
+}
+
+impl FromColumn for ForeignRowRef {
+    // Row index followed by a key nothing uses
+    const WIDTH: usize = 16;
+
+    fn from_column(bytes: &[u8], _: &[u8]) -> Option<Self> {
+        let index = u64_at(bytes, 0)?;
+        Some(Self((index != NULL_ROW).then_some(index)))
+    }
+}
+
+/// Arrays are an element count and an offset into the variable data.
+impl<T: FromColumn> FromColumn for Vec<T> {
+    const WIDTH: usize = 16;
+
+    fn from_column(bytes: &[u8], variable: &[u8]) -> Option<Self> {
+        let count = u64_at(bytes, 0)? as usize;
+        let offset = u64_at(bytes, 8)? as usize;
+        let len = count.checked_mul(T::WIDTH)?;
+        let elements = variable.get(offset..offset.checked_add(len)?)?;
+
+        elements
+            .chunks_exact(T::WIDTH.max(1))
+            .take(count)
+            .map(|element| T::from_column(element, variable))
+            .collect()
+    }
+}
+
+/// Intervals are two values next to each other, like a min and max roll.
+impl<T: FromColumn> FromColumn for (T, T) {
+    const WIDTH: usize = T::WIDTH * 2;
+
+    fn from_column(bytes: &[u8], variable: &[u8]) -> Option<Self> {
+        let (min, max) = bytes.split_at(T::WIDTH);
+        Some((
+            T::from_column(min, variable)?,
+            T::from_column(max, variable)?,
+        ))
+    }
+}
@@ -0,0 +1,325 @@
+//! Column layouts from the community `dat-schema` JSON (`schema.min.json`),
+//! so tables can be read by column name without compiling the layout in.
+
+use std::{collections::HashMap, fmt};
+
+use serde::Deserialize;
+
+/// Game a table definition applies to, `validFor` is a bitmask of these
+const POE2: u32 = 2;
+
+/// Every table layout of a schema file, by table name.
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    tables: HashMap<String, TableSchema>,
+}
+
+/// Column layout of one table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSchema {
+    name: String,
+    columns: Vec<Column>,
+    row_width: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    name: Option<String>,
+    ty: ColumnType,
+    array: bool,
+    interval: bool,
+    offset: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Bool,
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+    String,
+    /// Row of the same table
+    Row,
+    /// Row of another table
+    ForeignRow,
+    /// Index into an enumeration
+    EnumRow,
+    /// Element type of an array whose contents aren't known yet
+    Unknown,
+}
+
+#[derive(Debug)]
+pub enum SchemaError {
+    Json(serde_json::Error),
+    /// A column type the layout can't be computed for
+    UnknownType {
+        table: String,
+        ty: String,
+    },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "invalid schema: {e}"),
+            Self::UnknownType { table, ty } => {
+                write!(f, "unknown column type {ty} in table {table}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Json(e) => Some(e),
+            Self::UnknownType { .. } => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for SchemaError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawSchema {
+    tables: Vec<RawTable>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawTable {
+    name: String,
+    #[serde(default)]
+    valid_for: Option<u32>,
+    columns: Vec<RawColumn>,
+}
+
+#[derive(Deserialize)]
+struct RawColumn {
+    name: Option<String>,
+    #[serde(rename = "type")]
+    ty: String,
+    #[serde(default)]
+    array: bool,
+    #[serde(default)]
+    interval: bool,
+}
+
+impl Schema {
+    /// Parses a `dat-schema` JSON file. Tables defined for both games keep the
+    /// Path of Exile 2 definition.
+    pub fn from_json(json: &str) -> Result<Self, SchemaError> {
+        let raw: RawSchema = serde_json::from_str(json)?;
+        let mut tables = HashMap::with_capacity(raw.tables.len());
+
+        for table in raw.tables {
+            let poe2 = table.valid_for.is_none_or(|valid| valid & POE2 != 0);
+            if !poe2 && tables.contains_key(&table.name.to_lowercase()) {
+                continue;
+            }
+
+            let key = table.name.to_lowercase();
+            tables.insert(key, TableSchema::new(table)?);
+        }
+
+        Ok(Self { tables })
+    }
+
+    /// The layout of `table`, e.g. `Mods`, ignoring case.
+    pub fn table(&self, table: &str) -> Option<&TableSchema> {
+        self.tables.get(&table.to_lowercase())
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = &TableSchema> {
+        self.tables.values()
+    }
+}
+
+impl TableSchema {
+    fn new(raw: RawTable) -> Result<Self, SchemaError> {
+        let mut offset = 0;
+        let mut columns = Vec::with_capacity(raw.columns.len());
+
+        for column in raw.columns {
+            let ty = ColumnType::parse(&column.ty).ok_or_else(|| SchemaError::UnknownType {
+                table: raw.name.clone(),
+                ty: column.ty.clone(),
+            })?;
+            if ty == ColumnType::Unknown && !column.array {
+                return Err(SchemaError::UnknownType {
+                    table: raw.name,
+                    ty: column.ty,
+                });
+            }
+
+            let column = Column {
+                name: column.name,
+                ty,
+                array: column.array,
+                interval: column.interval,
+                offset,
+            };
+            offset += column.width();
+            columns.push(column);
+        }
+
+        Ok(Self {
+            name: raw.name,
+            columns,
+            row_width: offset,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Looks up a column by name, ignoring case.
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|column| {
+            column
+                .name
+                .as_deref()
+                .is_some_and(|column| column.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Bytes of fixed data in every row.
+    pub fn row_width(&self) -> usize {
+        self.row_width
+    }
+}
+
+impl Column {
+    /// Unnamed columns are ones nobody has figured out yet.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn ty(&self) -> ColumnType {
+        self.ty
+    }
+
+    pub fn is_array(&self) -> bool {
+        self.array
+    }
+
+    /// Whether the column holds a pair of values, like a min and max roll.
+    pub fn is_interval(&self) -> bool {
+        self.interval
+    }
+
+    /// Byte offset of the column in the row.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Bytes the column takes up in the row. Arrays are an element count and
+    /// an offset into the variable data.
+    pub fn width(&self) -> usize {
+        match (self.array, self.interval) {
+            (true, _) => 16,
+            (false, true) => self.ty.width() * 2,
+            (false, false) => self.ty.width(),
+        }
+    }
+}
+
+impl ColumnType {
+    fn parse(ty: &str) -> Option<Self> {
+        Some(match ty {
+            "bool" => Self::Bool,
+            "i8" => Self::I8,
+            "u8" => Self::U8,
+            "i16" => Self::I16,
+            "u16" => Self::U16,
+            "i32" => Self::I32,
+            "u32" => Self::U32,
+            "i64" => Self::I64,
+            "u64" => Self::U64,
+            "f32" => Self::F32,
+            "f64" => Self::F64,
+            "string" => Self::String,
+            "row" => Self::Row,
+            "foreignrow" => Self::ForeignRow,
+            "enumrow" => Self::EnumRow,
+            "array" => Self::Unknown,
+            _ => return None,
+        })
+    }
+
+    /// Bytes a single value takes up in a row or an array.
+    pub fn width(self) -> usize {
+        match self {
+            Self::Bool | Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 | Self::EnumRow => 4,
+            Self::I64 | Self::U64 | Self::F64 | Self::String | Self::Row => 8,
+            // Row index followed by a key nothing uses
+            Self::ForeignRow => 16,
+            Self::Unknown => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{ColumnType, Schema};
+
+    pub(crate) const SCHEMA: &str = r#"{
+        "version": 3,
+        "tables": [
+            {
+                "validFor": 1,
+                "name": "Mods",
+                "columns": [{ "name": "Id", "type": "string", "array": false, "interval": false }]
+            },
+            {
+                "validFor": 2,
+                "name": "Mods",
+                "columns": [
+                    { "name": "Id", "type": "string", "array": false, "interval": false },
+                    { "name": null, "type": "i32", "array": false, "interval": false },
+                    { "name": "Level", "type": "u32", "array": false, "interval": false },
+                    { "name": "Stats", "type": "foreignrow", "array": true, "interval": false },
+                    { "name": "Roll", "type": "i32", "array": false, "interval": true },
+                    { "name": "Tags", "type": "array", "array": true, "interval": false }
+                ]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn from_json() {
+        let schema = Schema::from_json(SCHEMA).unwrap();
+        let mods = schema.table("mods").unwrap();
+        assert_eq!(mods.name(), "Mods");
+        assert_eq!(mods.columns().len(), 6);
+        assert_eq!(mods.row_width(), 8 + 4 + 4 + 16 + 8 + 16);
+
+        let level = mods.column("level").unwrap();
+        assert_eq!(level.ty(), ColumnType::U32);
+        assert_eq!(level.offset(), 12);
+        assert_eq!(mods.column("Roll").unwrap().width(), 8);
+        assert!(mods.column("Missing").is_none());
+
+        let unknown =
+            r#"{ "tables": [{ "name": "T", "columns": [{ "name": null, "type": "x" }] }] }"#;
+        assert!(Schema::from_json(unknown).is_err());
+    }
+}
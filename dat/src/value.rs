@@ -0,0 +1,203 @@
+use crate::{
+    schema::{Column, ColumnType},
+    DatError,
+};
+
+/// Row references that don't point anywhere are filled with this
+pub(crate) const NULL_ROW: u64 = 0xFEFE_FEFE_FEFE_FEFE;
+
+/// A column value read with a [`Column`]'s layout.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    I8(i8),
+    U8(u8),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    String(String),
+    /// Index of a row in the same table, `None` for a null reference
+    Row(Option<u64>),
+    /// Index of a row in the referenced table, `None` for a null reference
+    ForeignRow(Option<u64>),
+    EnumRow(u32),
+    Array(Vec<Value>),
+    /// A pair of values, like a min and max roll
+    Interval(Box<(Value, Value)>),
+}
+
+/// Reads `column` out of `row`, following strings and arrays into `variable`.
+/// `None` if anything points out of bounds, or for arrays whose element type
+/// isn't known.
+pub(crate) fn read(row: &[u8], column: &Column, variable: &[u8]) -> Option<Value> {
+    let bytes = row.get(column.offset()..column.offset() + column.width())?;
+    let ty = column.ty();
+
+    if column.is_array() {
+        let count = u64_at(bytes, 0)? as usize;
+        let offset = u64_at(bytes, 8)? as usize;
+        if ty == ColumnType::Unknown {
+            return (count == 0).then(|| Value::Array(vec![]));
+        }
+
+        let width = ty.width();
+        let elements = variable.get(offset..offset.checked_add(count.checked_mul(width)?)?)?;
+        return elements
+            .chunks_exact(width)
+            .map(|element| scalar(element, ty, variable))
+            .collect::<Option<_>>()
+            .map(Value::Array);
+    }
+
+    if column.is_interval() {
+        let (min, max) = bytes.split_at(ty.width());
+        let min = scalar(min, ty, variable)?;
+        let max = scalar(max, ty, variable)?;
+        return Some(Value::Interval(Box::new((min, max))));
+    }
+
+    scalar(bytes, ty, variable)
+}
+
+fn scalar(bytes: &[u8], ty: ColumnType, variable: &[u8]) -> Option<Value> {
+    let row = |index| (index != NULL_ROW).then_some(index);
+
+    Some(match ty {
+        ColumnType::Bool => Value::Bool(*bytes.first()? != 0),
+        ColumnType::I8 => Value::I8(i8::from_le_bytes(array(bytes)?)),
+        ColumnType::U8 => Value::U8(u8::from_le_bytes(array(bytes)?)),
+        ColumnType::I16 => Value::I16(i16::from_le_bytes(array(bytes)?)),
+        ColumnType::U16 => Value::U16(u16::from_le_bytes(array(bytes)?)),
+        ColumnType::I32 => Value::I32(i32::from_le_bytes(array(bytes)?)),
+        ColumnType::U32 => Value::U32(u32::from_le_bytes(array(bytes)?)),
+        ColumnType::I64 => Value::I64(i64::from_le_bytes(array(bytes)?)),
+        ColumnType::U64 => Value::U64(u64_at(bytes, 0)?),
+        ColumnType::F32 => Value::F32(f32::from_le_bytes(array(bytes)?)),
+        ColumnType::F64 => Value::F64(f64::from_le_bytes(array(bytes)?)),
+        ColumnType::String => Value::String(string(variable, u64_at(bytes, 0)? as usize)?),
+        ColumnType::Row => Value::Row(row(u64_at(bytes, 0)?)),
+        ColumnType::ForeignRow => Value::ForeignRow(row(u64_at(bytes, 0)?)),
+        ColumnType::EnumRow => Value::EnumRow(u32::from_le_bytes(array(bytes)?)),
+        ColumnType::Unknown => return None,
+    })
+}
+
+/// Writes `value` into `column` of `row`. Strings and array elements are
+/// appended to `variable` and the row is pointed at them.
+pub(crate) fn write(
+    row: &mut [u8],
+    column: &Column,
+    value: &Value,
+    variable: &mut Vec<u8>,
+) -> Result<(), DatError> {
+    let mismatch = || DatError::ColumnValue {
+        offset: column.offset(),
+        ty: column.ty(),
+    };
+    let bytes = row
+        .get_mut(column.offset()..column.offset() + column.width())
+        .ok_or_else(mismatch)?;
+    let ty = column.ty();
+
+    match value {
+        Value::Array(elements) if column.is_array() && ty != ColumnType::Unknown => {
+            let width = ty.width();
+            let mut data = vec![0; elements.len() * width];
+            for (element, out) in elements.iter().zip(data.chunks_exact_mut(width)) {
+                write_scalar(element, ty, out, variable).ok_or_else(mismatch)?;
+            }
+            // Written after any strings the elements point to
+            let offset = push(variable, &data);
+            bytes[..8].copy_from_slice(&(elements.len() as u64).to_le_bytes());
+            bytes[8..].copy_from_slice(&offset.to_le_bytes());
+            Ok(())
+        }
+        Value::Interval(interval) if column.is_interval() => {
+            let (min, max) = bytes.split_at_mut(ty.width());
+            write_scalar(&interval.0, ty, min, variable).ok_or_else(mismatch)?;
+            write_scalar(&interval.1, ty, max, variable).ok_or_else(mismatch)
+        }
+        _ if column.is_array() || column.is_interval() => Err(mismatch()),
+        _ => write_scalar(value, ty, bytes, variable).ok_or_else(mismatch),
+    }
+}
+
+/// Writes a single value into the `ty.width()` bytes of `out`. `None` if the
+/// value isn't of type `ty`.
+fn write_scalar(
+    value: &Value,
+    ty: ColumnType,
+    out: &mut [u8],
+    variable: &mut Vec<u8>,
+) -> Option<()> {
+    let row = |index: Option<u64>| index.unwrap_or(NULL_ROW).to_le_bytes();
+
+    match (value, ty) {
+        (Value::Bool(v), ColumnType::Bool) => out[0] = *v as u8,
+        (Value::I8(v), ColumnType::I8) => out.copy_from_slice(&v.to_le_bytes()),
+        (Value::U8(v), ColumnType::U8) => out.copy_from_slice(&v.to_le_bytes()),
+        (Value::I16(v), ColumnType::I16) => out.copy_from_slice(&v.to_le_bytes()),
+        (Value::U16(v), ColumnType::U16) => out.copy_from_slice(&v.to_le_bytes()),
+        (Value::I32(v), ColumnType::I32) => out.copy_from_slice(&v.to_le_bytes()),
+        (Value::U32(v), ColumnType::U32) => out.copy_from_slice(&v.to_le_bytes()),
+        (Value::I64(v), ColumnType::I64) => out.copy_from_slice(&v.to_le_bytes()),
+        (Value::U64(v), ColumnType::U64) => out.copy_from_slice(&v.to_le_bytes()),
+        (Value::F32(v), ColumnType::F32) => out.copy_from_slice(&v.to_le_bytes()),
+        (Value::F64(v), ColumnType::F64) => out.copy_from_slice(&v.to_le_bytes()),
+        (Value::String(v), ColumnType::String) => {
+            out.copy_from_slice(&push_string(variable, v).to_le_bytes())
+        }
+        (Value::Row(v), ColumnType::Row) => out.copy_from_slice(&row(*v)),
+        // The key after the index is left as it was, null rows null both
+        (Value::ForeignRow(v), ColumnType::ForeignRow) => {
+            out[..8].copy_from_slice(&row(*v));
+            if v.is_none() {
+                out[8..].copy_from_slice(&row(None));
+            }
+        }
+        (Value::EnumRow(v), ColumnType::EnumRow) => out.copy_from_slice(&v.to_le_bytes()),
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Appends `data` to the variable data, returning its offset.
+pub(crate) fn push(variable: &mut Vec<u8>, data: &[u8]) -> u64 {
+    let offset = variable.len() as u64;
+    variable.extend_from_slice(data);
+    offset
+}
+
+/// Appends `s` as a null-terminated UTF-16 string, returning its offset.
+pub(crate) fn push_string(variable: &mut Vec<u8>, s: &str) -> u64 {
+    let offset = variable.len() as u64;
+    for unit in s.encode_utf16().chain([0]) {
+        variable.extend_from_slice(&unit.to_le_bytes());
+    }
+    offset
+}
+
+/// Reads the null-terminated UTF-16 string at `offset` of the variable data.
+pub(crate) fn string(variable: &[u8], offset: usize) -> Option<String> {
+    let units: Vec<u16> = variable
+        .get(offset..)?
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+
+    Some(String::from_utf16_lossy(&units))
+}
+
+pub(crate) fn array<const N: usize>(bytes: &[u8]) -> Option<[u8; N]> {
+    bytes.get(..N)?.try_into().ok()
+}
+
+pub(crate) fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    array(bytes.get(offset..)?).map(u64::from_le_bytes)
+}
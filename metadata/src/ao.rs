@@ -1,13 +1,17 @@
 mod ast;
 
 use crate::core::{
-    bom, expr, extends, is_abstract,
-    tokens::{Expr, Span},
+    bom,
+    error::PResult,
+    expr, extends, is_abstract,
+    tokens::{Expr, Position, Span},
     version,
 };
-use nom::{combinator::opt, multi::many0, sequence::Tuple, IResult};
+use nom::{combinator::opt, multi::many0, sequence::Tuple};
 
-pub fn parse_ao<'a>(input: Span<'a>) -> IResult<Span<'a>, AO<'a>> {
+/// Parses the `.ao` family of metadata files. `.aoc`, `.it` and `.ot` share the
+/// same header and block grammar and only differ in their block types.
+pub fn parse_ao<'a>(input: Span<'a>) -> PResult<'a, AO<'a>> {
     (opt(bom), version, opt(is_abstract), extends, many0(expr))
         .parse(input)
         .map(|(input, (_, version, is_abstract, extends, blocks))| {
@@ -21,9 +25,6 @@ pub fn parse_ao<'a>(input: Span<'a>) -> IResult<Span<'a>, AO<'a>> {
                 },
             )
         })
-        .inspect(|e| {
-            dbg!(&e);
-        })
 }
 
 #[derive(Debug)]
@@ -34,6 +35,50 @@ pub struct AO<'a> {
     children: Vec<Expr<'a>>,
 }
 
+impl<'a> AO<'a> {
+    /// Looks up a nested value by a `/` separated path of block types or names
+    /// and assignment keys, e.g. `Mods/enable_rarity`. The first match wins at
+    /// every level. Gives the block for a block and the value for a key.
+    pub fn get(&self, path: &str) -> Option<&Expr<'a>> {
+        let mut children = self.children.as_slice();
+        let mut found = None;
+
+        for segment in path.split('/') {
+            let expr = children.iter().find_map(|child| child.named(segment))?;
+            children = match expr {
+                Expr::Block(block) => block.values.as_slice(),
+                _ => &[],
+            };
+            found = Some(expr);
+        }
+
+        found
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn is_abstract(&self) -> bool {
+        self.is_abstract
+    }
+
+    /// Path of the parent file, `None` for `extends "nothing"`
+    pub fn extends(&self) -> Option<&'a str> {
+        self.extends.as_ref().map(|ext| *ext.data())
+    }
+
+    /// Position of the `extends` path, to jump to the parent from
+    pub fn extends_position(&self) -> Option<Position> {
+        self.extends.as_ref().map(Position::from)
+    }
+
+    /// The top-level blocks and expressions
+    pub fn children(&self) -> &[Expr<'a>] {
+        &self.children
+    }
+}
+
 // impl AO<'_> {
 //     pub fn visit(&self, visitor: &mut impl Visitor) {
 //         visitor.visit_version(self.version);
@@ -48,7 +93,10 @@ pub struct AO<'a> {
 #[cfg(test)]
 mod tests {
     use super::parse_ao;
-    use crate::{core::tokens::Span, util::read_string_from_utf16};
+    use crate::{
+        core::tokens::{Expr, Lit, Span},
+        util::read_string_from_utf16,
+    };
 
     // #[test]
     // fn base() {
@@ -68,18 +116,55 @@ mod tests {
         let src = include_bytes!("../resources/equipment.it");
         let src = read_string_from_utf16(src);
 
-        // let (input, ao) = parse_ao(Span::new(&src)).unwrap();
-        // dbg!(&ao);
+        let (input, ao) = parse_ao(Span::new(&src, true)).unwrap();
+        assert!(input.is_empty(), "unparsed input: {:?}", input.data());
 
-        // assert_eq!(ao.version, 2);
-        // assert_eq!(ao.is_abstract, true);
-        // assert_eq!(ao.extends, Some("Metadata/Items/Item"));
-        // assert_eq!(ao.children.len(), 3);
+        assert_eq!(ao.version, 2);
+        assert!(ao.is_abstract);
+        assert_eq!(
+            ao.extends.map(|ext| *ext.data()),
+            Some("Metadata/Items/Item")
+        );
+        assert_eq!(ao.children.len(), 3);
+
+        assert!(matches!(ao.get("Base"), Some(Expr::Block(block)) if block.values.is_empty()));
+        assert!(matches!(
+            ao.get("Mods/enable_rarity"),
+            Some(Expr::Lit(Lit::Str(s))) if s.value == "normal"
+        ));
+        assert!(ao.get("Mods/enable_rarity/normal").is_none());
+        assert!(ao.get("Mods/missing").is_none());
+
+        let extends = ao.extends_position().unwrap();
+        assert_eq!((extends.line, extends.column), (3, 10));
+        let Some(Expr::Block(mods)) = ao.get("Mods") else {
+            panic!("expected a block");
+        };
+        assert_eq!(mods.block_type(), "Mods");
+        assert_eq!(mods.position().line, 8);
+        let Expr::Assign(list) = &mods.values()[0] else {
+            panic!("expected an assignment");
+        };
+        assert_eq!(list.left().sym(), "stat_description_list");
+        assert_eq!((list.position().line, list.position().column), (10, 2));
+        assert_eq!(list.right().position().line, 10);
+    }
+    #[test]
+    fn item() {
+        let src = include_bytes!("../resources/item.it");
+        let src = read_string_from_utf16(src);
+
+        let (input, ao) = parse_ao(Span::new(&src, true)).unwrap();
+        assert!(input.is_empty(), "unparsed input: {:?}", input.data());
+
+        assert!(ao.is_abstract);
+        assert!(ao.extends.is_none());
+        assert_eq!(ao.children.len(), 1);
     }
     #[test]
     fn character() {
         let src = include_bytes!("../resources/character.ao");
-        let src = read_string_from_utf16(src);
+        let _src = read_string_from_utf16(src);
 
         // let (input, ao) = parse_ao(Span::new(&src)).unwrap();
         // dbg!(&ao);
@@ -94,22 +179,50 @@ mod tests {
         let src = include_bytes!("../resources/character.aoc");
         let src = read_string_from_utf16(src);
 
-        // let (input, ao) = parse_ao(Span::new(&src, false)).unwrap();
-        // dbg!(&ao);
+        let (input, ao) = parse_ao(Span::new(&src, true)).unwrap();
+        assert!(input.is_empty(), "unparsed input: {:?}", input.data());
 
-        // assert_eq!(ao.version, 2);
-        // assert_eq!(ao.is_abstract, false);
-        // assert_eq!(ao.extends, Some("Metadata/Parent"));
-        // assert_eq!(ao.children.len(), 3);
+        assert_eq!(ao.version, 2);
+        assert!(!ao.is_abstract);
+        assert_eq!(ao.extends.map(|ext| *ext.data()), Some("Metadata/Parent"));
+        assert_eq!(ao.children.len(), 13);
+
+        let Expr::Block(controller) = &ao.children[0] else {
+            panic!("expected a block");
+        };
+        assert_eq!(controller.r#type, "ClientAnimationController");
+        // Socket properties are indented under their socket, but stay flat
+        let sockets = controller
+            .values
+            .iter()
+            .filter(|value| matches!(value, Expr::Assign(assign) if assign.left.sym == "socket"))
+            .count();
+        assert_eq!(sockets, 50);
     }
     #[test]
     fn gravestone_aoc() {
         let src = include_bytes!("../resources/gravestoneamuletheld.aoc");
         let src = read_string_from_utf16(src);
 
-        // assert_eq!(ao.version, 2);
-        // assert_eq!(ao.is_abstract, false);
-        // assert_eq!(ao.extends, Some("Metadata/Parent"));
-        // assert_eq!(ao.children.len(), 3);
+        let (input, ao) = parse_ao(Span::new(&src, true)).unwrap();
+        assert!(input.is_empty(), "unparsed input: {:?}", input.data());
+
+        assert_eq!(ao.version, 2);
+        assert!(!ao.is_abstract);
+        assert_eq!(ao.extends.map(|ext| *ext.data()), Some("Metadata/Parent"));
+        assert_eq!(ao.children.len(), 3);
+
+        // Event scripts are multi-line strings
+        let Expr::Block(events) = &ao.children[2] else {
+            panic!("expected a block");
+        };
+        assert_eq!(events.r#type, "BaseAnimationEvents");
+        let [Expr::Assign(script)] = events.values.as_slice() else {
+            panic!("expected a single assignment");
+        };
+        assert_eq!(script.left.sym, "on_attachment_chain_complete");
+        assert!(
+            matches!(&*script.right, Expr::Lit(Lit::Str(s)) if s.value.contains("PlayAnimation( strint );"))
+        );
     }
 }
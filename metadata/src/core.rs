@@ -1,29 +1,30 @@
+pub(crate) mod error;
 pub(crate) mod tokens;
 
+use error::{Error, PResult};
 use nom::{
     branch::{alt, permutation},
-    bytes::{
-        complete::{escaped, take_until},
-        streaming::is_not,
-    },
+    bytes::complete::{escaped, is_a, is_not, take_till},
     character::complete::{
-        alphanumeric1, digit1, line_ending, multispace0, multispace1, not_line_ending, one_of,
-        space0, space1,
+        alphanumeric1, digit1, hex_digit1, multispace0, multispace1, one_of, space0, space1,
     },
-    combinator::{consumed, map, map_parser, map_res, opt, recognize, value},
+    combinator::{consumed, map, map_res, opt, recognize, value, verify},
+    error::{ErrorKind, ParseError},
     multi::{many0, many1},
-    number::complete::{double, float},
+    number::complete::recognize_float,
     sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
-    IResult,
 };
-use nom_supreme::tag::complete::{tag, tag_no_case};
+use nom_supreme::tag::{
+    complete::{tag, tag_no_case},
+    TagError,
+};
 use tokens::{BoolLit, Expr, ExprAssign, ExprBlock, FloatLit, Ident, IntLit, Lit, Span, StrLit};
 
-pub fn bom(input: Span) -> IResult<Span, bool> {
+pub fn bom(input: Span) -> PResult<bool> {
     alt((value(true, tag("\u{FEFF}")), value(false, tag("\u{FFFE}"))))(input)
 }
 
-pub fn version(input: Span) -> IResult<Span, u32> {
+pub fn version(input: Span) -> PResult<u32> {
     terminated(
         preceded(
             tag("version"),
@@ -33,11 +34,11 @@ pub fn version(input: Span) -> IResult<Span, u32> {
     )(input)
 }
 
-pub fn is_abstract(input: Span) -> IResult<Span, bool> {
+pub fn is_abstract(input: Span) -> PResult<bool> {
     terminated(tag("abstract"), multispace1)(input).map(|(s, _)| (s, true))
 }
 
-pub fn extends(input: Span) -> IResult<Span, Option<Span>> {
+pub fn extends(input: Span) -> PResult<Option<Span>> {
     terminated(
         preceded(
             tag("extends"),
@@ -49,20 +50,35 @@ pub fn extends(input: Span) -> IResult<Span, Option<Span>> {
     )(input)
 }
 
-pub fn expr_block(input: Span) -> IResult<Span, ExprBlock> {
-    map(
-        tuple((
-            opt(comment),
-            terminated(not_line_ending, multispace1),
-            map(opt(tag("//")), |v| v.is_some()),
-            delimited(
-                terminated(tag("{"), alt((space1, line_ending))),
-                many0(expr),
-                tag("}"),
-            ),
-        )),
-        |(doc, span, commented_out, values, ..)| ExprBlock {
-            r#type: &span,
+/// Parses a block and its children. Once the opening `{` is found this is
+/// committed to being a block, a child that doesn't parse fails the whole file
+/// with that child's error and the block's type attached.
+pub fn expr_block(input: Span) -> PResult<ExprBlock> {
+    let (rest, (doc, span, commented_out, _)) = tuple((
+        opt(comment),
+        terminated(line, multispace1),
+        map(opt(tag("//")), |v| v.is_some()),
+        terminated(tag("{"), multispace0),
+    ))(input)
+    // Not a block, the other expressions explain better why this didn't parse
+    .map_err(|e| e.map(|_| Error::from_error_kind(input, ErrorKind::Tag)))?;
+    let r#type = span.trim_end();
+
+    let (rest, values) = many0(expr)(rest)?;
+    let (rest, _) = tag("}")(rest).map_err(|_: nom::Err<Error>| {
+        // Reparse the child many0 stopped at to find out why it failed
+        let mut error = match expr(rest) {
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) if !rest.trim().is_empty() => e,
+            _ => Error::from_tag(rest, "}"),
+        };
+        error.block.get_or_insert(r#type);
+        nom::Err::Failure(error)
+    })?;
+
+    Ok((
+        rest,
+        ExprBlock {
+            r#type,
             // comment,
             values,
             name: None,
@@ -71,13 +87,10 @@ pub fn expr_block(input: Span) -> IResult<Span, ExprBlock> {
             doc,
             commented_out,
         },
-    )(input)
-    // .inspect_err(|e| {
-    //     dbg!(e);
-    // })
+    ))
 }
 
-pub fn expr(input: Span) -> IResult<Span, Expr> {
+pub fn expr(input: Span) -> PResult<Expr> {
     delimited(
         multispace0,
         alt((
@@ -89,13 +102,11 @@ pub fn expr(input: Span) -> IResult<Span, Expr> {
     )(input)
 }
 
-pub fn comment(input: Span) -> IResult<Span, Comment> {
-    map(preceded(tag("//"), not_line_ending), |value| Comment {
-        value,
-    })(input)
+pub fn comment(input: Span) -> PResult<Comment> {
+    map(preceded(tag("//"), line), |value| Comment { value })(input)
 }
 
-fn lit(input: Span) -> IResult<Span, Lit> {
+fn lit(input: Span) -> PResult<Lit> {
     alt((
         map(str_lit, Lit::from),
         map(float_lit, Lit::from),
@@ -104,7 +115,7 @@ fn lit(input: Span) -> IResult<Span, Lit> {
     ))(input)
 }
 
-pub fn expr_assign(input: Span) -> IResult<Span, ExprAssign> {
+pub fn expr_assign(input: Span) -> PResult<ExprAssign> {
     map(
         tuple((
             map(opt(pair(tag("//"), space0)), |v| v.is_some()),
@@ -127,7 +138,13 @@ pub fn expr_assign(input: Span) -> IResult<Span, ExprAssign> {
     )(input)
 }
 
-fn valid_string(input: Span) -> IResult<Span, Span> {
+/// The rest of the line, stopping at either `\r` or `\n` so a stray `\r`
+/// from CRLF files never ends up in the parsed text.
+fn line(input: Span) -> PResult<Span> {
+    take_till(|c| c == '\r' || c == '\n')(input)
+}
+
+fn valid_string(input: Span) -> PResult<Span> {
     delimited(
         tag("\""),
         escaped(is_not("\"\\"), '\\', one_of(r#""\rnt"#)),
@@ -135,27 +152,48 @@ fn valid_string(input: Span) -> IResult<Span, Span> {
     )(input)
 }
 
-fn str_lit(input: Span) -> IResult<Span, StrLit<'_>> {
+fn str_lit(input: Span<'_>) -> PResult<'_, StrLit<'_>> {
     map(valid_string, |span| StrLit { value: &span, span })(input)
 }
-fn float_lit(input: Span) -> IResult<Span, FloatLit<'_>> {
-    alt((
-        map(consumed(double), |(span, value)| FloatLit { value, span }),
-        map(consumed(float), |(span, value)| FloatLit {
-            value: value as f64,
-            span,
-        }),
-    ))(input)
+/// Floats need a fraction or an exponent (`1.5`, `-0.5`, `1e-3`) so plain
+/// integers are left to [`int_lit`]. A trailing `f` is allowed.
+fn float_lit(input: Span<'_>) -> PResult<'_, FloatLit<'_>> {
+    map(
+        terminated(
+            consumed(map_res(
+                verify(recognize_float, |span: &Span| {
+                    span.contains(['.', 'e', 'E'])
+                }),
+                |span: Span| span.parse::<f64>(),
+            )),
+            opt(one_of("fF")),
+        ),
+        |(span, value)| FloatLit { value, span },
+    )(input)
 }
 
-fn int_lit(input: Span) -> IResult<Span, IntLit<'_>> {
+fn int_lit(input: Span<'_>) -> PResult<'_, IntLit<'_>> {
     map(
-        consumed(map_res(digit1, |span: Span| span.parse::<i64>())),
-        |(span, value)| IntLit { value, span },
+        consumed(pair(
+            opt(one_of("+-")),
+            alt((
+                map_res(preceded(tag_no_case("0x"), hex_digit1), |span: Span| {
+                    i64::from_str_radix(span.data(), 16)
+                }),
+                map_res(preceded(tag_no_case("0b"), is_a("01")), |span: Span| {
+                    i64::from_str_radix(span.data(), 2)
+                }),
+                map_res(digit1, |span: Span| span.parse::<i64>()),
+            )),
+        )),
+        |(span, (sign, value))| IntLit {
+            value: if sign == Some('-') { -value } else { value },
+            span,
+        },
     )(input)
 }
 
-fn bool_lit(input: Span) -> IResult<Span, BoolLit<'_>> {
+fn bool_lit(input: Span<'_>) -> PResult<'_, BoolLit<'_>> {
     map(
         consumed(alt((
             value(true, tag_no_case("true")),
@@ -170,6 +208,86 @@ pub struct Comment<'a> {
     value: Span<'a>,
 }
 
+impl<'a> Comment<'a> {
+    /// Text after the `//`
+    pub fn value(&self) -> &'a str {
+        self.value.data()
+    }
+
+    pub fn position(&self) -> tokens::Position {
+        (&self.value).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        expr_assign, expr_block, lit,
+        tokens::{Expr, Lit},
+        Span,
+    };
+
+    fn parse(src: &str) -> Lit<'_> {
+        let (rest, lit) = lit(Span::new(src, true)).unwrap();
+        assert!(rest.is_empty(), "unparsed input: {:?}", rest.data());
+        lit
+    }
+
+    #[test]
+    fn float() {
+        assert!(matches!(parse("-0.5"), Lit::Float(f) if f.value == -0.5));
+        assert!(matches!(parse("1e-3"), Lit::Float(f) if f.value == 1e-3));
+        assert!(matches!(parse("1.5e3"), Lit::Float(f) if f.value == 1500.0));
+        assert!(matches!(parse("2.0f"), Lit::Float(f) if f.value == 2.0));
+    }
+
+    #[test]
+    fn int() {
+        assert!(matches!(parse("42"), Lit::Int(i) if i.value == 42));
+        assert!(matches!(parse("0xDEADBEEF"), Lit::Int(i) if i.value == 0xDEADBEEF));
+        assert!(matches!(parse("0xff"), Lit::Int(i) if i.value == 0xFF));
+        assert!(matches!(parse("0b101"), Lit::Int(i) if i.value == 5));
+    }
+
+    #[test]
+    fn string() {
+        let Lit::Str(plain) = parse(r#""Metadata/Parent""#) else {
+            panic!("expected a string literal");
+        };
+        assert_eq!(plain.decoded(), "Metadata/Parent");
+
+        let Lit::Str(escaped) = parse(r#""say \"hi\"\n\tnow\\""#) else {
+            panic!("expected a string literal");
+        };
+        assert_eq!(escaped.value, r#"say \"hi\"\n\tnow\\"#);
+        assert_eq!(escaped.decoded(), "say \"hi\"\n\tnow\\");
+    }
+
+    #[test]
+    fn negative_int() {
+        assert!(matches!(parse("-5"), Lit::Int(i) if i.value == -5));
+        assert!(matches!(parse("+5"), Lit::Int(i) if i.value == 5));
+        assert!(matches!(parse("-0x10"), Lit::Int(i) if i.value == -16));
+
+        let (_, assign) = expr_assign(Span::new("armour = -5", true)).unwrap();
+        assert_eq!(assign.left.sym, "armour");
+        assert!(matches!(*assign.right, Expr::Lit(Lit::Int(i)) if i.value == -5));
+    }
+
+    #[test]
+    fn crlf() {
+        let src = "Base  \r\n{\r\n\tbase_level = 1 \r\n\ttag = \"default\"\r\n\r}\r\n";
+        let (rest, block) = expr_block(Span::new(src, true)).unwrap();
+        assert_eq!(*rest.data(), "\r\n");
+        assert_eq!(block.r#type, "Base");
+        assert_eq!(block.values.len(), 2);
+
+        let (_, empty) = expr_block(Span::new("Lights\r\n{\r\r\n}", true)).unwrap();
+        assert_eq!(empty.r#type, "Lights");
+        assert!(empty.values.is_empty());
+    }
+}
+
 // #[cfg(test)]
 // mod tests {
 //     // Handle mutliple different cases, not really sure what they are atm
@@ -0,0 +1,60 @@
+use nom::{
+    error::{ErrorKind, FromExternalError, ParseError},
+    IResult,
+};
+use nom_supreme::tag::TagError;
+
+use super::tokens::Span;
+
+pub(crate) type PResult<'a, T> = IResult<Span<'a>, T, Error<'a>>;
+
+/// Parser error that remembers the tag that was expected and the innermost
+/// block it happened in, so a bad child can be pointed at instead of just the
+/// block around it.
+#[derive(Debug, Clone)]
+pub(crate) struct Error<'a> {
+    pub input: Span<'a>,
+    pub kind: ErrorKind,
+    pub expected: Option<&'static str>,
+    pub block: Option<&'a str>,
+}
+
+impl<'a> ParseError<Span<'a>> for Error<'a> {
+    fn from_error_kind(input: Span<'a>, kind: ErrorKind) -> Self {
+        Self {
+            input,
+            kind,
+            expected: None,
+            block: None,
+        }
+    }
+
+    fn append(_: Span<'a>, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    /// Keeps whichever alternative got furthest, that's the one that best
+    /// explains what the input was meant to be.
+    fn or(self, other: Self) -> Self {
+        if other.input.byte_offset() >= self.input.byte_offset() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+impl<'a, E> FromExternalError<Span<'a>, E> for Error<'a> {
+    fn from_external_error(input: Span<'a>, kind: ErrorKind, _: E) -> Self {
+        Self::from_error_kind(input, kind)
+    }
+}
+
+impl<'a> TagError<Span<'a>, &'static str> for Error<'a> {
+    fn from_tag(input: Span<'a>, tag: &'static str) -> Self {
+        Self {
+            expected: Some(tag),
+            ..Self::from_error_kind(input, ErrorKind::Tag)
+        }
+    }
+}
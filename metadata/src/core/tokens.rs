@@ -1,30 +1,74 @@
+use std::borrow::Cow;
+
 use nom_span::Spanned;
 
 use super::Comment;
 
 pub(crate) type Span<'a> = Spanned<&'a str>;
 
+/// Where a node starts in the decoded source, for pointing editors at it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    /// 1-based
+    pub line: usize,
+    /// 1-based, in characters
+    pub column: usize,
+    /// Byte offset
+    pub offset: usize,
+}
+
+impl From<&Span<'_>> for Position {
+    fn from(span: &Span<'_>) -> Self {
+        Self {
+            line: span.line(),
+            column: span.col(),
+            offset: span.byte_offset(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub(crate) enum Expr<'a> {
+pub enum Expr<'a> {
     Block(ExprBlock<'a>),
     Assign(ExprAssign<'a>),
     Lit(Lit<'a>),
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct Ident<'a> {
-    pub sym: &'a str,
-    pub span: Span<'a>,
+pub struct Ident<'a> {
+    pub(crate) sym: &'a str,
+    pub(crate) span: Span<'a>,
+}
+
+impl<'a> Ident<'a> {
+    pub fn sym(&self) -> &'a str {
+        self.sym
+    }
+
+    pub fn position(&self) -> Position {
+        (&self.span).into()
+    }
 }
 
 #[derive(Debug, Clone)]
-pub(crate) enum Lit<'a> {
+pub enum Lit<'a> {
     Str(StrLit<'a>),
     Int(IntLit<'a>),
     Float(FloatLit<'a>),
     Bool(BoolLit<'a>),
 }
 
+impl Lit<'_> {
+    pub fn position(&self) -> Position {
+        match self {
+            Lit::Str(lit) => lit.position(),
+            Lit::Int(lit) => lit.position(),
+            Lit::Float(lit) => lit.position(),
+            Lit::Bool(lit) => lit.position(),
+        }
+    }
+}
+
 impl<'a> From<Lit<'a>> for Expr<'a> {
     fn from(value: Lit<'a>) -> Self {
         Expr::Lit(value)
@@ -32,9 +76,46 @@ impl<'a> From<Lit<'a>> for Expr<'a> {
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct StrLit<'a> {
-    pub value: &'a str,
-    pub span: Span<'a>,
+pub struct StrLit<'a> {
+    pub(crate) value: &'a str,
+    pub(crate) span: Span<'a>,
+}
+
+impl<'a> StrLit<'a> {
+    /// The string as written, without the quotes and escapes left as is
+    pub fn value(&self) -> &'a str {
+        self.value
+    }
+
+    /// Position of the opening quote's contents
+    pub fn position(&self) -> Position {
+        (&self.span).into()
+    }
+
+    /// The string with its escape sequences (`\"`, `\\`, `\n`, `\r`, `\t`) decoded.
+    pub fn decoded(&self) -> Cow<'_, str> {
+        if !self.value.contains('\\') {
+            return Cow::Borrowed(self.value);
+        }
+
+        let mut decoded = String::with_capacity(self.value.len());
+        let mut chars = self.value.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                decoded.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('n') => decoded.push('\n'),
+                Some('r') => decoded.push('\r'),
+                Some('t') => decoded.push('\t'),
+                Some(c) => decoded.push(c),
+                None => decoded.push('\\'),
+            }
+        }
+        Cow::Owned(decoded)
+    }
 }
 
 impl<'a> From<StrLit<'a>> for Lit<'a> {
@@ -44,9 +125,19 @@ impl<'a> From<StrLit<'a>> for Lit<'a> {
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct IntLit<'a> {
-    pub value: i64,
-    pub span: Span<'a>,
+pub struct IntLit<'a> {
+    pub(crate) value: i64,
+    pub(crate) span: Span<'a>,
+}
+
+impl IntLit<'_> {
+    pub fn value(&self) -> i64 {
+        self.value
+    }
+
+    pub fn position(&self) -> Position {
+        (&self.span).into()
+    }
 }
 
 impl<'a> From<IntLit<'a>> for Lit<'a> {
@@ -56,9 +147,19 @@ impl<'a> From<IntLit<'a>> for Lit<'a> {
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct FloatLit<'a> {
-    pub value: f64,
-    pub span: Span<'a>,
+pub struct FloatLit<'a> {
+    pub(crate) value: f64,
+    pub(crate) span: Span<'a>,
+}
+
+impl FloatLit<'_> {
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    pub fn position(&self) -> Position {
+        (&self.span).into()
+    }
 }
 
 impl<'a> From<FloatLit<'a>> for Lit<'a> {
@@ -68,9 +169,19 @@ impl<'a> From<FloatLit<'a>> for Lit<'a> {
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct BoolLit<'a> {
-    pub value: bool,
-    pub span: Span<'a>,
+pub struct BoolLit<'a> {
+    pub(crate) value: bool,
+    pub(crate) span: Span<'a>,
+}
+
+impl BoolLit<'_> {
+    pub fn value(&self) -> bool {
+        self.value
+    }
+
+    pub fn position(&self) -> Position {
+        (&self.span).into()
+    }
 }
 
 impl<'a> From<BoolLit<'a>> for Lit<'a> {
@@ -80,15 +191,47 @@ impl<'a> From<BoolLit<'a>> for Lit<'a> {
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct ExprBlock<'a> {
-    pub name: Option<StrLit<'a>>,
-    pub r#type: &'a str,
-    pub extends: Option<&'a str>,
-    pub doc: Option<Comment<'a>>,
+pub struct ExprBlock<'a> {
+    pub(crate) name: Option<StrLit<'a>>,
+    pub(crate) r#type: &'a str,
+    pub(crate) extends: Option<&'a str>,
+    pub(crate) doc: Option<Comment<'a>>,
     // pub comment: Option<Comment<'a>>,
-    pub values: Vec<Expr<'a>>,
-    pub span: Span<'a>,
-    pub commented_out: bool,
+    pub(crate) values: Vec<Expr<'a>>,
+    pub(crate) span: Span<'a>,
+    pub(crate) commented_out: bool,
+}
+
+impl<'a> ExprBlock<'a> {
+    /// The block's type, e.g. `Base` or `ClientAnimationController`
+    pub fn block_type(&self) -> &'a str {
+        self.r#type
+    }
+
+    pub fn name(&self) -> Option<&StrLit<'a>> {
+        self.name.as_ref()
+    }
+
+    pub fn extends(&self) -> Option<&'a str> {
+        self.extends
+    }
+
+    pub fn doc(&self) -> Option<&Comment<'a>> {
+        self.doc.as_ref()
+    }
+
+    pub fn values(&self) -> &[Expr<'a>] {
+        &self.values
+    }
+
+    pub fn is_commented_out(&self) -> bool {
+        self.commented_out
+    }
+
+    /// Position of the block's type
+    pub fn position(&self) -> Position {
+        (&self.span).into()
+    }
 }
 
 impl<'a> From<ExprBlock<'a>> for Expr<'a> {
@@ -98,12 +241,61 @@ impl<'a> From<ExprBlock<'a>> for Expr<'a> {
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct ExprAssign<'a> {
-    pub left: Ident<'a>,
-    pub right: Box<Expr<'a>>,
-    pub doc: Option<Comment<'a>>,
+pub struct ExprAssign<'a> {
+    pub(crate) left: Ident<'a>,
+    pub(crate) right: Box<Expr<'a>>,
+    pub(crate) doc: Option<Comment<'a>>,
     // pub comment: Option<Comment<'a>>,
-    pub commented_out: bool,
+    pub(crate) commented_out: bool,
+}
+
+impl<'a> ExprAssign<'a> {
+    /// The key being assigned
+    pub fn left(&self) -> &Ident<'a> {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Expr<'a> {
+        &self.right
+    }
+
+    pub fn doc(&self) -> Option<&Comment<'a>> {
+        self.doc.as_ref()
+    }
+
+    pub fn is_commented_out(&self) -> bool {
+        self.commented_out
+    }
+
+    /// Position of the key
+    pub fn position(&self) -> Position {
+        self.left.position()
+    }
+}
+
+impl<'a> Expr<'a> {
+    pub fn position(&self) -> Position {
+        match self {
+            Expr::Block(block) => block.position(),
+            Expr::Assign(assign) => assign.position(),
+            Expr::Lit(lit) => lit.position(),
+        }
+    }
+
+    /// Matches a path segment of [`AO::get`](crate::AO::get) against this
+    /// expression: a block by its type or name, giving the block itself, or
+    /// an assignment by its key, giving its value.
+    pub(crate) fn named(&self, name: &str) -> Option<&Expr<'a>> {
+        match self {
+            Expr::Block(block)
+                if block.r#type == name || block.name.as_ref().is_some_and(|n| n.value == name) =>
+            {
+                Some(self)
+            }
+            Expr::Assign(assign) if assign.left.sym == name => Some(&assign.right),
+            _ => None,
+        }
+    }
 }
 
 impl<'a> From<ExprAssign<'a>> for Expr<'a> {
@@ -0,0 +1,161 @@
+use std::fmt;
+
+use nom::error::{ErrorKind, ParseError as _};
+
+use crate::{
+    ao::{parse_ao, AO},
+    core::{error::Error, expr, tokens::Span},
+    util::decode,
+};
+
+/// A parsed metadata file that owns its text, so the [`AO`] borrowing from it
+/// doesn't have to be tied to a `String` the caller keeps around.
+pub struct Document {
+    /// Borrows from `source`. Declared first so it's dropped first, and only
+    /// handed out with the lifetime of `&self`
+    ao: AO<'static>,
+    /// Never changed, so the text `ao` points into stays where it is even
+    /// when the `Document` is moved
+    source: String,
+}
+
+impl Document {
+    fn from_source(source: String) -> Result<Self, ParseError> {
+        // SAFETY: `source`'s heap buffer is never written to or freed while
+        // `ao` is alive, see the fields
+        let text: &'static str = unsafe { &*(source.as_str() as *const str) };
+        let ao = parse_complete(text)?;
+
+        Ok(Self { ao, source })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The parsed file.
+    pub fn ao(&self) -> &AO<'_> {
+        &self.ao
+    }
+}
+
+impl Clone for Document {
+    fn clone(&self) -> Self {
+        // The clone's tree has to point into its own copy of the text
+        Self::from_source(self.source.clone()).expect("source parsed before")
+    }
+}
+
+impl fmt::Debug for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Document").field("ao", &self.ao).finish()
+    }
+}
+
+/// Parses the whole file. Fails if anything is left over after the last
+/// block.
+fn parse_complete(source: &str) -> Result<AO<'_>, ParseError> {
+    let (rest, ao) = parse_ao(Span::new(source, true)).map_err(ParseError::from)?;
+
+    if !rest.data().trim().is_empty() {
+        // Find out why the next top-level expression didn't parse
+        return Err(match expr(rest) {
+            Err(e) => e.into(),
+            Ok(_) => Error::from_error_kind(rest, ErrorKind::Eof).into(),
+        });
+    }
+
+    Ok(ao)
+}
+
+/// Decodes `bytes`, detecting their encoding, and parses them. The returned
+/// [`Document`] owns the decoded text, [`Document::ao`] gives the parsed file.
+pub fn parse(bytes: &[u8]) -> Result<Document, ParseError> {
+    Document::from_source(decode(bytes))
+}
+
+/// Where and why a metadata file failed to parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line of the input that couldn't be parsed, 0 if unknown
+    pub line: usize,
+    pub column: usize,
+    pub kind: ErrorKind,
+    /// Tag that was expected at that point, e.g. `=` or `}`
+    pub expected: Option<&'static str>,
+    /// Type of the innermost block the error happened in
+    pub block: Option<String>,
+}
+
+impl From<Error<'_>> for ParseError {
+    fn from(value: Error<'_>) -> Self {
+        Self {
+            line: value.input.line(),
+            column: value.input.col(),
+            kind: value.kind,
+            expected: value.expected,
+            block: value.block.map(str::to_string),
+        }
+    }
+}
+
+impl From<nom::Err<Error<'_>>> for ParseError {
+    fn from(value: nom::Err<Error<'_>>) -> Self {
+        match value {
+            nom::Err::Error(e) | nom::Err::Failure(e) => e.into(),
+            nom::Err::Incomplete(_) => Self {
+                line: 0,
+                column: 0,
+                kind: ErrorKind::Eof,
+                expected: None,
+                block: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.block {
+            Some(block) => write!(f, "block `{block}` failed")?,
+            None => write!(f, "parse error")?,
+        }
+        write!(f, " at line {}, column {}: ", self.line, self.column)?;
+        match self.expected {
+            Some(expected) => write!(f, "expected `{expected}`"),
+            None => write!(f, "{}", self.kind.description()),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn document() {
+        let document = parse(include_bytes!("../resources/equipment.it")).unwrap();
+        assert!(document.source().starts_with("version 2"));
+
+        let ao = document.ao();
+        assert_eq!(ao.extends(), Some("Metadata/Items/Item"));
+        assert_eq!(document.clone().ao().extends(), ao.extends());
+
+        let utf8 = parse(b"version 2\nextends \"nothing\"\n\nBase\n{\n}\n").unwrap();
+        assert_eq!(utf8.ao().extends(), None);
+
+        let error = parse(b"version 2\nextends \"nothing\"\n\nBase\n{\n").unwrap_err();
+        assert_eq!(error.expected, Some("}"));
+        assert_eq!(error.block.as_deref(), Some("Base"));
+
+        let src = b"version 2\nextends \"nothing\"\n\nBase\n{\n\ttag = \"a\"\n\tlevel 1\n}\n";
+        let error = parse(src).unwrap_err();
+        assert_eq!((error.line, error.column), (7, 8));
+        assert_eq!(
+            error.to_string(),
+            "block `Base` failed at line 7, column 8: expected `=`"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexSet;
+
+use crate::Document;
+
+/// Inheritance hierarchy of metadata objects. Nodes are object paths
+/// normalized by [`object_path`], every edge points from an object to the one
+/// it `extends`.
+#[derive(Debug, Clone, Default)]
+pub struct Graph {
+    nodes: IndexSet<String>,
+    /// `(child, parent)` indices into `nodes`
+    edges: Vec<(usize, usize)>,
+}
+
+impl Graph {
+    pub fn nodes(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().map(String::as_str)
+    }
+
+    /// Every `(child, parent)` pair.
+    pub fn edges(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.edges
+            .iter()
+            .map(|&(child, parent)| (self.nodes[child].as_str(), self.nodes[parent].as_str()))
+    }
+
+    /// The object `path` extends, if any.
+    pub fn parent(&self, path: &str) -> Option<&str> {
+        let node = self.nodes.get_index_of(&object_path(path))?;
+        self.edges
+            .iter()
+            .find(|&&(child, _)| child == node)
+            .map(|&(_, parent)| self.nodes[parent].as_str())
+    }
+
+    /// Every object that inherits from `path`, directly or through others, in
+    /// breadth-first order. These are the objects a change to `path` affects.
+    pub fn dependents(&self, path: &str) -> Vec<&str> {
+        let Some(root) = self.nodes.get_index_of(&object_path(path)) else {
+            return vec![];
+        };
+
+        let mut seen = IndexSet::from([root]);
+        let mut i = 0;
+        while let Some(&node) = seen.get_index(i) {
+            for &(child, _) in self.edges.iter().filter(|&&(_, parent)| parent == node) {
+                seen.insert(child);
+            }
+            i += 1;
+        }
+
+        seen.iter()
+            .skip(1)
+            .map(|&node| self.nodes[node].as_str())
+            .collect()
+    }
+
+    fn node(&mut self, path: String) -> usize {
+        self.nodes.insert_full(path).0
+    }
+
+    #[cfg(feature = "petgraph")]
+    pub fn to_petgraph(&self) -> petgraph::graph::DiGraph<String, ()> {
+        use petgraph::graph::{DiGraph, NodeIndex};
+
+        let mut graph = DiGraph::with_capacity(self.nodes.len(), self.edges.len());
+        for node in &self.nodes {
+            graph.add_node(node.clone());
+        }
+        for &(child, parent) in &self.edges {
+            graph.add_edge(NodeIndex::new(child), NodeIndex::new(parent), ());
+        }
+        graph
+    }
+}
+
+/// Builds the graph of `extends` references of parsed files, keyed by the path
+/// each was read from. Objects that are only ever extended still get a node.
+pub fn build_graph(parsed: &[(PathBuf, Document)]) -> Graph {
+    let mut graph = Graph::default();
+
+    for (path, document) in parsed {
+        let child = object_path(&path.to_string_lossy());
+        let parent = document
+            .ao()
+            .extends()
+            .map(|extends| resolve(&child, extends));
+
+        let child = graph.node(child);
+        if let Some(parent) = parent {
+            let parent = graph.node(parent);
+            graph.edges.push((child, parent));
+        }
+    }
+
+    graph
+}
+
+/// Normalizes an object path the way the game compares them: `/` separated,
+/// lowercase and without the file extension, e.g.
+/// `Metadata\Characters\Character.ao` becomes `metadata/characters/character`.
+/// Anything before the first `Metadata` directory is dropped, so paths under
+/// an extraction directory like `out/Metadata/...` match their `extends`.
+pub fn object_path(path: &str) -> String {
+    let path = path.replace('\\', "/").to_lowercase();
+    let path = path.trim_start_matches('/');
+    let path = match path.starts_with("metadata/") {
+        true => path,
+        false => path.find("/metadata/").map_or(path, |i| &path[i + 1..]),
+    };
+    let stem = Path::new(path).with_extension("");
+    stem.to_string_lossy().into_owned()
+}
+
+/// Resolves `extends` of the object at `child`. Paths under `Metadata/` are
+/// absolute, anything else is relative to the child's directory.
+fn resolve(child: &str, extends: &str) -> String {
+    let extends = object_path(extends);
+    if extends.starts_with("metadata/") {
+        return extends;
+    }
+
+    match child.rsplit_once('/') {
+        Some((dir, _)) => format!("{dir}/{extends}"),
+        None => extends,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{build_graph, object_path};
+    use crate::parse;
+
+    #[test]
+    fn graph() {
+        let files = [
+            ("Metadata/Parent.ao", "nothing"),
+            ("Metadata/Characters/Character.ao", "Metadata/Parent"),
+            (
+                "Metadata\\Characters\\Str\\Str.ao",
+                "Metadata/Characters/Character",
+            ),
+            ("Metadata/Characters/Dex.ao", "Character"),
+        ];
+        let documents: Vec<_> = files
+            .iter()
+            .map(|(path, extends)| {
+                let src = format!("version 2\nextends \"{extends}\"\n");
+                (PathBuf::from(path), parse(src.as_bytes()).unwrap())
+            })
+            .collect();
+        let graph = build_graph(&documents);
+        assert_eq!(graph.nodes().count(), 4);
+        assert_eq!(graph.edges().count(), 3);
+        assert_eq!(graph.parent("Metadata/Parent"), None);
+        assert_eq!(
+            graph.parent("metadata/characters/dex.ao"),
+            Some("metadata/characters/character")
+        );
+        assert_eq!(
+            graph.dependents("Metadata/Parent"),
+            [
+                "metadata/characters/character",
+                "metadata/characters/str/str",
+                "metadata/characters/dex"
+            ]
+        );
+        assert_eq!(
+            object_path("/Metadata\\Items\\Item.it"),
+            "metadata/items/item"
+        );
+        assert_eq!(
+            object_path("out/Metadata/Items/Item.it"),
+            "metadata/items/item"
+        );
+    }
+
+    #[test]
+    fn extracted() {
+        let files = [
+            ("out/Metadata/Characters/Character.ao", "nothing"),
+            (
+                "out/Metadata/Characters/Str.ao",
+                "Metadata/Characters/Character",
+            ),
+            ("C:\\poe2\\Metadata\\Characters\\Dex.ao", "Character"),
+        ];
+        let documents: Vec<_> = files
+            .iter()
+            .map(|(path, extends)| {
+                let src = format!("version 2\nextends \"{extends}\"\n");
+                (PathBuf::from(path), parse(src.as_bytes()).unwrap())
+            })
+            .collect();
+        let graph = build_graph(&documents);
+        assert_eq!(graph.nodes().count(), 3);
+        assert_eq!(
+            graph.dependents("Metadata/Characters/Character"),
+            ["metadata/characters/str", "metadata/characters/dex"]
+        );
+    }
+}
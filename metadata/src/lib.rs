@@ -1,5 +1,40 @@
 mod ao;
 mod core;
-mod datc64;
+mod document;
+mod graph;
+#[cfg(feature = "serde")]
+mod ser;
 mod util;
+// Not wired up yet, see the commented out `AO::visit`
+#[allow(dead_code)]
 mod visitor;
+
+/// The parsed syntax tree of a metadata file, read-only.
+///
+/// ```
+/// use metadata::ast::{Expr, Lit};
+///
+/// let document = metadata::parse(b"version 2\nextends \"nothing\"\n\nBase\n{\n\ttag = \"default\"\n}\n").unwrap();
+/// let ao = document.ao();
+///
+/// let Expr::Block(base) = &ao.children()[0] else { panic!() };
+/// for value in base.values() {
+///     if let Expr::Assign(assign) = value {
+///         assert_eq!(assign.left().sym(), "tag");
+///         assert!(matches!(assign.right(), Expr::Lit(Lit::Str(s)) if s.value() == "default"));
+///     }
+/// }
+/// ```
+pub mod ast {
+    pub use crate::ao::AO;
+    pub use crate::core::{
+        tokens::{
+            BoolLit, Expr, ExprAssign, ExprBlock, FloatLit, Ident, IntLit, Lit, Position, StrLit,
+        },
+        Comment,
+    };
+}
+
+pub use ao::AO;
+pub use document::{parse, Document, ParseError};
+pub use graph::{build_graph, object_path, Graph};
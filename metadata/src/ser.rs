@@ -0,0 +1,129 @@
+//! JSON-friendly serialization of the syntax tree. Positions are left out.
+//! Blocks and assignments tell apart by their `type` and `key` fields, and
+//! literals serialize as plain values with string escapes decoded.
+
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+use crate::{
+    ao::AO,
+    core::{
+        tokens::{Expr, ExprAssign, ExprBlock, Lit},
+        Comment,
+    },
+};
+
+impl Serialize for AO<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AO", 4)?;
+        state.serialize_field("version", &self.version())?;
+        state.serialize_field("abstract", &self.is_abstract())?;
+        state.serialize_field("extends", &self.extends())?;
+        state.serialize_field("children", self.children())?;
+        state.end()
+    }
+}
+
+impl Serialize for Expr<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Expr::Block(block) => block.serialize(serializer),
+            Expr::Assign(assign) => assign.serialize(serializer),
+            Expr::Lit(lit) => lit.serialize(serializer),
+        }
+    }
+}
+
+impl Serialize for ExprBlock<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ExprBlock", 6)?;
+        state.serialize_field("type", self.block_type())?;
+        optional(&mut state, "name", self.name().map(|name| name.decoded()))?;
+        optional(&mut state, "extends", self.extends())?;
+        optional(&mut state, "doc", self.doc())?;
+        optional(
+            &mut state,
+            "commented_out",
+            self.is_commented_out().then_some(true),
+        )?;
+        state.serialize_field("values", self.values())?;
+        state.end()
+    }
+}
+
+impl Serialize for ExprAssign<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ExprAssign", 4)?;
+        state.serialize_field("key", self.left().sym())?;
+        state.serialize_field("value", self.right())?;
+        optional(&mut state, "doc", self.doc())?;
+        optional(
+            &mut state,
+            "commented_out",
+            self.is_commented_out().then_some(true),
+        )?;
+        state.end()
+    }
+}
+
+impl Serialize for Lit<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Lit::Str(lit) => serializer.serialize_str(&lit.decoded()),
+            Lit::Int(lit) => serializer.serialize_i64(lit.value()),
+            Lit::Float(lit) => serializer.serialize_f64(lit.value()),
+            Lit::Bool(lit) => serializer.serialize_bool(lit.value()),
+        }
+    }
+}
+
+impl Serialize for Comment<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.value())
+    }
+}
+
+/// Leaves the field out instead of writing `null`, most blocks have none of
+/// these.
+fn optional<S: SerializeStruct>(
+    state: &mut S,
+    key: &'static str,
+    value: Option<impl Serialize>,
+) -> Result<(), S::Error> {
+    match value {
+        Some(value) => state.serialize_field(key, &value),
+        None => state.skip_field(key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+
+    #[test]
+    fn json() {
+        let src =
+            b"version 2\nextends \"nothing\"\n\nBase\n{\n\ttag = \"a\\\"b\"\n\tlevel = 3\n}\n";
+        let document = parse(src).unwrap();
+        let json = serde_json::to_value(document.ao()).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "version": 2,
+                "abstract": false,
+                "extends": null,
+                "children": [{
+                    "type": "Base",
+                    "values": [
+                        { "key": "tag", "value": "a\"b" },
+                        { "key": "level", "value": 3 },
+                    ],
+                }],
+            })
+        );
+
+        let document = parse(include_bytes!("../resources/equipment.it")).unwrap();
+        let json = serde_json::to_value(document.ao()).unwrap();
+        assert_eq!(json["extends"], "Metadata/Items/Item");
+    }
+}
@@ -1,3 +1,4 @@
+#[cfg(test)]
 pub fn read_string_from_utf16(src: &[u8]) -> String {
     let chunks = src.chunks_exact(2);
     assert_eq!(0, chunks.remainder().len());
@@ -8,3 +9,34 @@ pub fn read_string_from_utf16(src: &[u8]) -> String {
 
     String::from_utf16(&buffer).unwrap()
 }
+
+/// Decodes a metadata file, going by its byte order mark and falling back to
+/// UTF-16LE when the second byte is zero, like the game's files that start
+/// with an ASCII `version`, or to UTF-8. The BOM is stripped.
+pub fn decode(src: &[u8]) -> String {
+    let (encoding, bom_len) = encoding_rs::Encoding::for_bom(src).unwrap_or_else(|| {
+        // Only the header is sure to be ASCII, strings later on can be anything
+        let utf16 = src.get(1) == Some(&0);
+        if utf16 {
+            (encoding_rs::UTF_16LE, 0)
+        } else {
+            (encoding_rs::UTF_8, 0)
+        }
+    });
+
+    let (text, _) = encoding.decode_without_bom_handling(&src[bom_len..]);
+    text.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode;
+
+    #[test]
+    fn utf16_without_bom() {
+        let text = "version 2\nextends \"nothing\"\n// Ω ★\n";
+        let utf16: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(decode(&utf16), text);
+        assert_eq!(decode(text.as_bytes()), text);
+    }
+}
@@ -1,19 +1,168 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 use tracing::Level;
 
 #[derive(Debug, Parser)]
+#[command(subcommand_negates_reqs = true)]
 pub struct Commands {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Game install to extract from. Comma-separated installs are layered, e.g.
+    /// a base install and then a patch, with bundles read from the last one
+    /// that has them
+    #[arg(long, short, required = true, value_delimiter = ',')]
+    pub input: Vec<PathBuf>,
+    /// Output directory, or with `--extract` the file to write, `-` for stdout
+    #[arg(long, short, required = true)]
+    pub output: Option<PathBuf>,
+    /// Also extract shadercache bundles and files
     #[arg(long, short)]
-    pub input: PathBuf,
+    pub shaders: bool,
+    /// Skip files that already exist in the output with the expected size
     #[arg(long, short)]
-    pub output: PathBuf,
+    pub resume: bool,
+    /// Decompress one seek chunk at a time to bound memory use, at some cost to speed
+    #[arg(long)]
+    pub streaming: bool,
+    /// Link files with identical contents to the first copy instead of writing them again
+    #[arg(long, value_enum)]
+    pub dedup: Option<Dedup>,
+    /// Times to retry reading a bundle or writing a file after a transient error
+    #[arg(long, default_value_t = 2)]
+    pub retries: u32,
+    /// Number of worker threads, 0 or omitted uses all cores
+    #[arg(
+        long,
+        short,
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(u16).range(0..=256),
+    )]
+    pub threads: Option<u16>,
+    /// Write every file straight into the output by its file name, numbering collisions
+    #[arg(long)]
+    pub flat: bool,
+    /// Set the modification time of every extracted file, in seconds since the Unix epoch
+    #[arg(long, value_name = "EPOCH")]
+    pub mtime: Option<u64>,
+    /// Comma-separated globs to extract, prefix a glob with `!` to exclude it
     #[arg(long, short)]
-    pub shaders: Option<bool>,
+    pub filter: Option<String>,
+    /// Comma-separated file extensions to extract, checked without globs
+    #[arg(long)]
+    pub ext: Option<String>,
+    /// Content digest of every extracted file to record in the manifest
+    #[arg(long, value_enum, default_value_t = Hash::None)]
+    pub hash: Hash,
+    /// Write a JSON manifest of every extracted file to this path
     #[arg(long, short)]
-    pub threads: Option<u8>,
+    pub manifest: Option<PathBuf>,
+    /// Also write the path of every file hash, or `UNKNOWN` for hashes without
+    /// one, to this file, e.g. `hashes.txt`
+    #[arg(long)]
+    pub hashes: Option<PathBuf>,
+    /// Write every missing bundle and unresolved path hash to this file
+    #[arg(long)]
+    pub errors_file: Option<PathBuf>,
+    /// Print every file that would be extracted and the total size, without
+    /// reading bundles or writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Extract a single file by its exact virtual path, writing it to `output`
     #[arg(long, short)]
-    pub filter: Option<String>,
-    #[arg(long, short, default_value_t = Level::INFO)]
-    pub debug: Level,
+    pub extract: Option<String>,
+    /// Log level for messages printed to stderr, defaults to `RUST_LOG` or warnings
+    #[arg(long, short)]
+    pub debug: Option<Level>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Print index and bundle statistics
+    Info {
+        #[arg(long, short)]
+        input: PathBuf,
+    },
+    /// Compress a file, or every file under a directory one after another in
+    /// path order, into a single bundle
+    Pack {
+        #[arg(long, short)]
+        input: PathBuf,
+        /// Bundle to write, e.g. `out.bundle.bin`
+        #[arg(long, short)]
+        output: PathBuf,
+        /// Oodle compressor for every chunk
+        #[arg(long, short, value_enum, default_value_t = Compressor::Hydra)]
+        compressor: Compressor,
+    },
+    /// Parse a metadata file like `.ao` or `.it`, UTF-16 or UTF-8, and print
+    /// its syntax tree
+    Metadata {
+        /// Metadata file, e.g. `character.ao`
+        input: PathBuf,
+        #[arg(long, short, value_enum, default_value_t = Format::Json)]
+        format: Format,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// Indented JSON
+    Json,
+    /// JSON on a single line
+    JsonCompact,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Compressor {
+    None,
+    Kraken,
+    Leviathan,
+    Mermaid,
+    Selkie,
+    Hydra,
+}
+
+impl From<Compressor> for bundle::Compressor {
+    fn from(value: Compressor) -> Self {
+        match value {
+            Compressor::None => Self::None,
+            Compressor::Kraken => Self::Kraken,
+            Compressor::Leviathan => Self::Leviathan,
+            Compressor::Mermaid => Self::Mermaid,
+            Compressor::Selkie => Self::Selkie,
+            Compressor::Hydra => Self::Hydra,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Dedup {
+    Hardlink,
+    Symlink,
+}
+
+impl From<Dedup> for bundle::index::Dedup {
+    fn from(value: Dedup) -> Self {
+        match value {
+            Dedup::Hardlink => Self::Hardlink,
+            Dedup::Symlink => Self::Symlink,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Hash {
+    None,
+    Xxh3,
+    Blake3,
+}
+
+impl From<Hash> for bundle::index::ContentHash {
+    fn from(value: Hash) -> Self {
+        match value {
+            Hash::None => Self::None,
+            Hash::Xxh3 => Self::Xxh3,
+            Hash::Blake3 => Self::Blake3,
+        }
+    }
 }
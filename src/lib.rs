@@ -0,0 +1,4 @@
+//! Re-exports the `bundle` crate and its modules, so `poe2::bundle::Bundle`
+//! and `poe2::index::Index` resolve for code depending on this package.
+
+pub use bundle::{self, filter, index, prelude, Bundle, BundleError};
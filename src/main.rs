@@ -1,66 +1,161 @@
 mod cli;
 
-use bundle::{self, index::Index, Bundle};
+use bundle::{
+    self,
+    filter::{Extensions, Filter},
+    index::{write_extracted, Digest, Extracted},
+    prelude::*,
+};
 use clap::Parser;
-use cli::Commands;
+use cli::{Command, Commands, Format};
 use cliclack::{MultiProgress, ProgressBar};
 use fancy_duration::AsFancyDuration;
-use globset::{Glob, GlobSetBuilder};
 use human_repr::HumanCount;
 use rayon::iter::ParallelIterator;
-use std::{sync::Arc, time::Instant};
-use tracing::{field::Visit, Subscriber};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+use std::{
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+use tracing::{field::Visit, span, Subscriber};
+use tracing_subscriber::{
+    filter::LevelFilter, layer::SubscriberExt, registry::LookupSpan, util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+/// Number of slowest bundles listed after extracting
+const SLOWEST_BUNDLES: usize = 10;
 
 fn main() {
     let Commands {
+        command,
         input,
         output,
         shaders,
         threads,
         filter,
+        ext,
+        resume,
+        streaming,
+        dedup,
+        flat,
+        mtime,
+        retries,
+        extract,
+        manifest,
+        hash,
+        debug,
+        errors_file,
+        dry_run,
+        hashes,
         ..
     } = Commands::parse();
 
-    let shaders = shaders.unwrap_or_default();
+    match command {
+        Some(Command::Info { input }) => {
+            check_install(&input, true).unwrap_or_else(|e| fail(&e));
+            info(&input);
+            return;
+        }
+        Some(Command::Pack {
+            input,
+            output,
+            compressor,
+        }) => {
+            pack(&input, &output, compressor.into());
+            return;
+        }
+        Some(Command::Metadata { input, format }) => {
+            print_metadata(&input, format);
+            return;
+        }
+        None => {}
+    }
+    let (Some((input, overlays)), Some(output)) = (input.split_first(), output) else {
+        unreachable!("clap requires input and output without a subcommand");
+    };
+    // The newest index lists the bundles of every layer
+    let latest = overlays.last().unwrap_or(input);
+    let installs: Vec<&PathBuf> = std::iter::once(input).chain(overlays).collect();
+    for &install in &installs {
+        check_install(install, install == latest).unwrap_or_else(|e| fail(&e));
+    }
 
     let multiprogress = MultiProgress::new("Decompressing...");
     let progress = Arc::new(multiprogress.add(ProgressBar::new(0)));
     progress.start("Initializing..");
 
     let layer = CliClackProgressLayer::new(progress.clone());
+    let summary = Arc::new(Summary::default());
 
-    tracing_subscriber::registry().with(layer).init();
-    // tracing_subscriber::fmt()
-    //     .with_file(false)
-    //     .with_file(false)
-    //     .with_target(false)
-    //     .with_timer(UtcTime::rfc_3339())
-    //     .with_max_level(debug)
-    //     .compact();
-
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(threads.unwrap_or_default().into())
-        .build_global()
-        .unwrap();
+    // Only the printed messages are filtered, progress is driven by trace events
+    let log_filter = match debug {
+        Some(level) => EnvFilter::new(level.as_str()),
+        None => EnvFilter::builder()
+            .with_default_directive(LevelFilter::WARN.into())
+            .from_env_lossy(),
+    };
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .compact()
+        .with_filter(log_filter);
+
+    tracing_subscriber::registry()
+        .with(layer)
+        .with(SummaryLayer(summary.clone()))
+        .with(fmt)
+        .init();
+
+    // rayon picks the thread count itself when it isn't set
+    let mut pool = rayon::ThreadPoolBuilder::new();
+    if let Some(threads) = threads.filter(|&threads| threads > 0) {
+        pool = pool.num_threads(threads.into());
+    }
+    // The global pool can only be built once, keep the existing one if it's already up
+    if let Err(e) = pool.build_global() {
+        tracing::warn!("Using existing thread pool: {e}");
+    }
 
-    assert!(input.exists());
     progress.set_message("Reading Index...");
-    let file = std::fs::read(input.join("Bundles2").join("_.index.bin")).unwrap();
+    let index = Index::open(latest).unwrap_or_else(|e| fail(&e.to_string()));
+
+    if let Some(ref path) = extract {
+        progress.set_message(format!("Extracting {path}..."));
+        let data = index
+            .read_file_layered(&installs, path)
+            .unwrap_or_else(|e| fail(&e.to_string()));
 
-    let bundle: Bundle<Index> = bundle::Bundle::from_slice(file.as_slice()).unwrap();
-    progress.set_message("Decompressing Index...");
-    let index = bundle.decompress().unwrap();
+        // `-` pipes the file to stdout instead, progress goes to stderr
+        let written = if output == Path::new("-") {
+            std::io::stdout().lock().write_all(&data)
+        } else {
+            output
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(&output, &data))
+        };
+        written.unwrap_or_else(|e| fail(&format!("{}: {e}", output.display())));
+
+        progress.stop(format!(
+            "Extracted {path} | Bytes Written: {}",
+            data.len().human_count_bytes()
+        ));
+        multiprogress.stop();
+        return;
+    }
 
     progress.set_message("Calculating total amount of file...");
+    index.check_paths().unwrap_or_else(|e| fail(&e.to_string()));
 
-    let mut builder = GlobSetBuilder::new();
-    if let Some(ref filter) = filter {
-        filter.split(',').for_each(|pat| {
-            builder.add(Glob::new(pat).unwrap());
-        });
-    };
-    let pattern = builder.build().unwrap();
+    let pattern = filter
+        .as_deref()
+        .map(Filter::new)
+        .transpose()
+        .unwrap_or_else(|e| fail(&e.to_string()))
+        .unwrap_or_default();
+    let extensions = ext.as_deref().map(Extensions::new).unwrap_or_default();
 
     let iter = index
         .iter_bundles()
@@ -68,7 +163,9 @@ fn main() {
         .filter_map(|(bundle, files)| {
             let matching: Arc<[_]> = files
                 .iter()
-                .filter(|(path, _)| pattern.is_match(path.to_str().unwrap()))
+                .filter(|(path, _)| {
+                    (extensions.is_empty() || extensions.is_match(path)) && pattern.is_match(path)
+                })
                 .cloned()
                 .collect::<Vec<_>>()
                 .into();
@@ -85,29 +182,374 @@ fn main() {
         .map(|(_, files)| {
             files
                 .iter()
-                .filter(|(path, _)| {
-                    shaders
-                        || !path
-                            .components()
-                            .filter_map(|c| c.as_os_str().to_str())
-                            .any(|c| c.contains("shadercache"))
-                })
+                .filter(|(path, _)| shaders || !is_shader(path))
                 .count() as u64
         })
         .sum();
 
+    if dry_run {
+        let mut files: Vec<(PathBuf, u32)> = iter
+            .flat_map_iter(|(_, files)| {
+                files
+                    .iter()
+                    .filter(|(path, _)| shaders || !is_shader(path))
+                    .map(|(path, file)| (path.clone(), file.size()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        files.sort();
+
+        let mut stdout = BufWriter::new(std::io::stdout().lock());
+        for (path, size) in &files {
+            writeln!(stdout, "{}\t{size}", path.display()).unwrap();
+        }
+        stdout.flush().unwrap();
+
+        let bytes: u64 = files.iter().map(|&(_, size)| size as u64).sum();
+        progress.stop(format!(
+            "Dry run | Files: {} | Bytes: {}",
+            files.len(),
+            bytes.human_count_bytes()
+        ));
+        multiprogress.stop();
+        return;
+    }
+
     progress.set_length(total);
     progress.set_message("Starting...");
     let start = Instant::now();
 
-    let bytes = index.extract(iter, input, output, shaders);
+    let options = ExtractOptions {
+        shaders,
+        resume,
+        streaming,
+        dedup: dedup.map(Into::into).unwrap_or_default(),
+        hash: hash.into(),
+        flat,
+        mtime: mtime.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+        overlays: overlays.to_vec(),
+        retries,
+    };
+    let extraction = match manifest {
+        Some(ref manifest) => {
+            let extracted = Mutex::new(vec![]);
+            let extraction = index
+                .extract_with_progress(iter, input, output, &options, |file| {
+                    extracted.lock().unwrap().push((
+                        file.path.to_path_buf(),
+                        file.bundle.to_string(),
+                        file.size,
+                        file.digest,
+                    ))
+                })
+                .unwrap_or_else(|e| fail(&e.to_string()));
+
+            let mut extracted: Vec<(PathBuf, String, u64, Option<Digest>)> =
+                extracted.into_inner().unwrap();
+            extracted.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+            let entries = extracted
+                .iter()
+                .map(|&(ref path, ref bundle, size, digest)| Extracted {
+                    path,
+                    bundle,
+                    size,
+                    digest,
+                });
+            std::fs::File::create(manifest)
+                .and_then(|file| write_extracted(entries, BufWriter::new(file)))
+                .unwrap_or_else(|e| fail(&format!("{}: {e}", manifest.display())));
+            extraction
+        }
+        None => index
+            .extract(iter, input, output, &options)
+            .unwrap_or_else(|e| fail(&e.to_string())),
+    };
+
+    if let Some(hashes) = hashes {
+        std::fs::File::create(&hashes)
+            .and_then(|file| index.write_hashes(BufWriter::new(file)))
+            .unwrap_or_else(|e| fail(&format!("{}: {e}", hashes.display())));
+    }
 
     progress.stop(format!(
         "Extracted in {} | Bytes Written: {}",
         start.elapsed().fancy_duration(),
-        bytes.human_count_bytes()
+        extraction.bytes.human_count_bytes()
     ));
     multiprogress.stop();
+
+    let mut timings = summary.timings.lock().unwrap();
+    timings.sort_by_key(|&(_, elapsed)| std::cmp::Reverse(elapsed));
+    if !timings.is_empty() {
+        let slowest: Vec<_> = timings
+            .iter()
+            .take(SLOWEST_BUNDLES)
+            .map(|(bundle, elapsed)| format!("  {bundle}: {}", elapsed.fancy_duration()))
+            .collect();
+        cliclack::log::info(format!("Slowest bundles:\n{}", slowest.join("\n"))).unwrap();
+    }
+
+    let (mut missing, mut out_of_bounds, mut failed) = (vec![], vec![], vec![]);
+    for failure in &extraction.failed {
+        match failure.error {
+            ExtractError::MissingBundle => missing.push(failure),
+            ExtractError::OutOfBounds { .. } => out_of_bounds.push(failure),
+            ExtractError::Io(_) => failed.push(failure),
+        }
+    }
+    let orphans = index.orphan_paths();
+    let unresolved = index.unresolved_files();
+    if !missing.is_empty()
+        || !orphans.is_empty()
+        || !unresolved.is_empty()
+        || !out_of_bounds.is_empty()
+        || !failed.is_empty()
+    {
+        cliclack::log::warning(format!(
+            "{} bundles missing, {} orphan paths, {} hashes unresolved, {} files out of bounds, {} failed",
+            missing.len(),
+            orphans.len(),
+            unresolved.len(),
+            out_of_bounds.len(),
+            failed.len()
+        ))
+        .unwrap();
+    }
+    if let Some(errors_file) = errors_file {
+        let written = std::fs::File::create(&errors_file).and_then(|file| {
+            let mut file = BufWriter::new(file);
+            for failed in &missing {
+                writeln!(file, "missing bundle: {}", failed.bundle)?;
+            }
+            for path in &orphans {
+                writeln!(file, "orphan path: {path}")?;
+            }
+            for hash in &unresolved {
+                writeln!(file, "unresolved hash: {hash:016x}")?;
+            }
+            for failed in &out_of_bounds {
+                writeln!(file, "out of bounds: {failed}")?;
+            }
+            for failed in &failed {
+                writeln!(file, "failed: {failed}")?;
+            }
+            file.flush()
+        });
+        written.unwrap_or_else(|e| fail(&format!("{}: {e}", errors_file.display())));
+    }
+}
+
+/// Whether `path` is inside a shadercache directory, those are skipped unless
+/// `--shaders` is given.
+fn is_shader(path: &Path) -> bool {
+    path.components()
+        .filter_map(|c| c.as_os_str().to_str())
+        .any(|c| c.contains("shadercache"))
+}
+
+/// Checks that `path` looks like a game install before anything is read from
+/// it, with `Bundles2/_.index.bin` if `needs_index`.
+fn check_install(path: &Path, needs_index: bool) -> Result<(), String> {
+    let bundles = path.join("Bundles2");
+    if !path.is_dir() {
+        return Err(format!("{} isn't a directory", path.display()));
+    }
+    if !bundles.is_dir() {
+        return Err(format!(
+            "expected Bundles2 under {}; is this a PoE2 install directory?",
+            path.display()
+        ));
+    }
+    if needs_index && !bundles.join("_.index.bin").is_file() {
+        return Err(format!(
+            "expected Bundles2/_.index.bin under {}; is this a PoE2 install directory?",
+            path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Prints `message` as an error and exits with a nonzero code.
+fn fail(message: &str) -> ! {
+    cliclack::log::error(message).unwrap();
+    std::process::exit(1)
+}
+
+/// Writes the files at `input` into one bundle at `output`. A directory's files
+/// are concatenated in path order, their offsets are listed once it's written.
+fn pack(input: &Path, output: &Path, compressor: bundle::Compressor) {
+    let mut files = vec![];
+    collect_files(input, &mut files).unwrap_or_else(|e| fail(&e.to_string()));
+    files.sort();
+
+    let mut data = vec![];
+    let mut offsets = vec![];
+    for file in &files {
+        let bytes = std::fs::read(file).unwrap_or_else(|e| fail(&e.to_string()));
+        let name = file.strip_prefix(input).unwrap_or(file);
+        offsets.push(format!(
+            "  {}: {} +{}",
+            name.display(),
+            data.len(),
+            bytes.len()
+        ));
+        data.extend_from_slice(&bytes);
+    }
+
+    let size = data.len();
+    let bundle: Bundle<Vec<u8>> = Bundle::new_with(data, compressor, bundle::BLOCK_LEN as usize)
+        .unwrap_or_else(|e| fail(&e.to_string()));
+    let bytes = bundle.to_vec();
+    std::fs::write(output, &bytes).unwrap_or_else(|e| fail(&e.to_string()));
+
+    if input.is_dir() {
+        cliclack::note(input.display(), offsets.join("\n")).unwrap();
+    }
+    cliclack::log::success(format!(
+        "Packed {} files into {} | {} -> {}",
+        files.len(),
+        output.display(),
+        size.human_count_bytes(),
+        bytes.len().human_count_bytes()
+    ))
+    .unwrap();
+}
+
+/// Prints the syntax tree of the metadata file at `path` to stdout.
+fn print_metadata(path: &Path, format: Format) {
+    let bytes = std::fs::read(path).unwrap_or_else(|e| fail(&format!("{}: {e}", path.display())));
+    let document =
+        metadata::parse(&bytes).unwrap_or_else(|e| fail(&format!("{}: {e}", path.display())));
+    let ao = document.ao();
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    match format {
+        Format::Json => serde_json::to_writer_pretty(&mut out, ao),
+        Format::JsonCompact => serde_json::to_writer(&mut out, ao),
+    }
+    .unwrap_or_else(|e| fail(&e.to_string()));
+    writeln!(out)
+        .and_then(|_| out.flush())
+        .unwrap_or_else(|e| fail(&e.to_string()));
+}
+
+/// Every file at `path`, recursing into directories.
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(path)? {
+        collect_files(&entry?.path(), files)?;
+    }
+
+    Ok(())
+}
+
+fn info(input: &Path) {
+    let index = Index::open(input).unwrap_or_else(|e| fail(&e.to_string()));
+
+    let mut sizes = index.bundle_sizes();
+    sizes.sort_by_key(|&(_, size)| std::cmp::Reverse(size));
+    let largest: Vec<_> = sizes
+        .iter()
+        .take(10)
+        .map(|(path, size)| format!("  {path}: {}", size.human_count_bytes()))
+        .collect();
+    let stats = index
+        .bundle_stats(input)
+        .unwrap_or_else(|e| fail(&e.to_string()));
+    let compressors: Vec<_> = stats
+        .compressors
+        .iter()
+        .map(|&(id, count)| format!("  {}: {count}", bundle::compressor_name(id)))
+        .collect();
+
+    let summary = [
+        format!("Files: {}", index.total_files()),
+        format!("Bundles: {}", index.bundle_count()),
+        format!(
+            "Uncompressed: {}",
+            index.total_uncompressed_size().human_count_bytes()
+        ),
+        format!("Compressed: {}", stats.compressed.human_count_bytes()),
+        format!("Largest bundles:\n{}", largest.join("\n")),
+        format!("Compressors:\n{}", compressors.join("\n")),
+    ];
+    cliclack::note(input.display(), summary.join("\n")).unwrap();
+}
+
+/// What the extraction's tracing spans recorded, printed once it's done.
+#[derive(Default)]
+struct Summary {
+    /// How long every extracted bundle took to read, decompress and write
+    timings: Mutex<Vec<(String, Duration)>>,
+}
+
+/// Bundle an `extract_bundle` span is for, kept until it records its time
+struct SpanBundle(String);
+
+struct SummaryLayer(Arc<Summary>);
+
+impl<S> Layer<S> for SummaryLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &span::Attributes<'_>,
+        id: &span::Id,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let mut visitor = SpanVisitor::default();
+        attrs.record(&mut visitor);
+        if let (Some(bundle), Some(span)) = (visitor.bundle, ctx.span(id)) {
+            span.extensions_mut().insert(SpanBundle(bundle));
+        }
+    }
+
+    fn on_record(
+        &self,
+        id: &span::Id,
+        values: &span::Record<'_>,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let mut visitor = SpanVisitor::default();
+        values.record(&mut visitor);
+        let (Some(elapsed_ms), Some(span)) = (visitor.elapsed_ms, ctx.span(id)) else {
+            return;
+        };
+        let extensions = span.extensions();
+        if let Some(SpanBundle(bundle)) = extensions.get::<SpanBundle>() {
+            let elapsed = Duration::from_millis(elapsed_ms);
+            let mut timings = self.0.timings.lock().unwrap();
+            timings.push((bundle.clone(), elapsed));
+        }
+    }
+}
+
+/// Reads the fields of `extract_bundle` spans.
+#[derive(Default)]
+struct SpanVisitor {
+    bundle: Option<String>,
+    elapsed_ms: Option<u64>,
+}
+
+impl Visit for SpanVisitor {
+    fn record_debug(&mut self, _field: &tracing::field::Field, _value: &dyn std::fmt::Debug) {}
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        if field.name() == "bundle" {
+            self.bundle = Some(value.to_string());
+        }
+    }
+
+    fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+        if field.name() == "elapsed_ms" {
+            self.elapsed_ms = Some(value);
+        }
+    }
 }
 
 struct CliClackProgressLayer {